@@ -1,12 +1,33 @@
 use eframe::{egui, epi};
-use notify::{Event, RecursiveMode, Watcher};
+use notify::Event;
 use std::env::current_dir;
 use std::ffi::OsString;
 // use std::fs::read_dir;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::{Instant, SystemTime};
 
+use crate::misc::bookmarks::Bookmarks;
+use crate::misc::cleanup::Cleanup;
+use crate::misc::conflict::ConflictResolver;
+use crate::misc::dir_sizes::{DirSizes, DirStats};
+use crate::misc::disk_usage::DiskUsage;
+use crate::misc::duplicates::Duplicates;
 use crate::misc::fonts::setup_custom_fonts;
+use crate::misc::git::{Git, GitStatus};
+use crate::misc::index;
+use crate::misc::ipc;
+use crate::misc::jump::Jump;
+use crate::misc::mime::{FileType, MimeDb};
+use crate::misc::name;
+use crate::misc::open_with::Applications;
+use crate::misc::output::OutputLog;
+use crate::misc::places::{self, Device, Place};
+use crate::misc::properties::Properties;
+use crate::misc::selection::Selection;
+use crate::misc::tags::TagStore;
+use crate::misc::views::{self, View, Views};
 use crate::{ui, misc};
 use crate::ui::settings::Settings;
 
@@ -18,21 +39,87 @@ pub struct Themis {
   pub search: String,
   #[cfg_attr(feature = "persistence", serde(skip))]
   pub rename: Rename,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub conflicts: ConflictResolver,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub errors: ErrorLog,
+  #[cfg_attr(feature = "persistence", serde(with = "name::saved_path"))]
   pub current_path: std::path::PathBuf,
+  /// Pins saved before bookmarks existed, moved over in `setup`.
   pub pinned_dirs: Vec<std::path::PathBuf>,
+  pub bookmarks: Bookmarks,
+  #[cfg_attr(feature = "persistence", serde(with = "name::saved_path"))]
   pub last_path: std::path::PathBuf,
+  #[cfg_attr(feature = "persistence", serde(with = "name::saved_path"))]
   pub selected_path: std::path::PathBuf,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub selection: Selection,
   pub drive_list: Vec<OsString>,
   #[cfg_attr(feature = "persistence", serde(skip))]
+  pub places: Vec<Place>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub devices: Vec<Device>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub devices_receiver: crossbeam_channel::Receiver<Vec<Device>>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
   pub filesystem: mft_ntfs::Filesystem,
   #[cfg_attr(feature = "persistence", serde(skip))]
-  pub fs_receiver: crossbeam_channel::Receiver<mft_ntfs::Filesystem>,
+  pub fs_receiver: crossbeam_channel::Receiver<(mft_ntfs::Filesystem, DirSizes)>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub dir_sizes: DirSizes,
   #[cfg_attr(feature = "persistence", serde(skip))]
   pub dir_watcher: DirWatcher,
+  #[cfg_attr(feature = "persistence", serde(skip))]
   pub dir_entries: Vec<DirEntry>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
   pub search_results: Vec<DirEntry>,
   #[cfg_attr(feature = "persistence", serde(skip))]
   pub panel_open: PanelOpen,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub disk_usage: DiskUsage,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub duplicates: Duplicates,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub cleanup: Cleanup,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub tags: TagStore,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub properties: Option<Properties>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub mime: MimeDb,
+  /// The sniffed type of what the context menu is open for, so it isn't
+  /// read again on every frame the menu is shown.
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub menu_type: Option<(PathBuf, FileType)>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub applications: Applications,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub output: OutputLog,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub git: Git,
+  /// How the current directory is shown, see `misc::views`.
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub view: View,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub views: Views,
+  /// Visited directories for the jump dialog, kept in `visits.bin`.
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub jump: Jump,
+  /// Path given on the command line, wins over the saved location.
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub start_path: Option<PathBuf>,
+  /// Commands from other launches and the file manager service.
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub ipc_sender: crossbeam_channel::Sender<ipc::Command>,
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub ipc_receiver: crossbeam_channel::Receiver<ipc::Command>,
+  /// Whether this window owns the single instance socket.
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub ipc_serving: bool,
+  /// Set while we own `org.freedesktop.FileManager1`.
+  #[cfg(target_os = "linux")]
+  #[cfg_attr(feature = "persistence", serde(skip))]
+  pub file_manager: Option<zbus::blocking::Connection>,
   pub settings: Settings,
 }
 
@@ -54,40 +141,184 @@ impl Default for Themis {
     //     dir_entries.push(dir_entry);
     //   }
     // }
+    let current_path = current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let (ipc_sender, ipc_receiver) = crossbeam_channel::unbounded();
     Self {
-      navigation: current_dir().unwrap().to_str().unwrap().to_owned(),
+      navigation: current_path.to_string_lossy().into_owned(),
       search: String::new(),
       rename: Rename::default(),
+      conflicts: ConflictResolver::default(),
+      errors: ErrorLog::default(),
       pinned_dirs: Vec::new(),
-      current_path: current_dir().unwrap(),
+      bookmarks: Bookmarks::default(),
+      current_path: current_path.clone(),
       drive_list: Vec::new(),
-      last_path: current_dir().unwrap(),
-      selected_path: current_dir().unwrap(),
+      places: Vec::new(),
+      devices: Vec::new(),
+      devices_receiver: crossbeam_channel::unbounded().1,
+      last_path: current_path.clone(),
+      selected_path: current_path,
+      selection: Selection::default(),
       dir_entries: Vec::new(),
       search_results: Vec::new(),
       fs_receiver: crossbeam_channel::unbounded().1,
       dir_watcher: DirWatcher::default(),
       filesystem: mft_ntfs::Filesystem::new(),
+      dir_sizes: DirSizes::default(),
       panel_open: PanelOpen::Main,
+      disk_usage: DiskUsage::default(),
+      duplicates: Duplicates::default(),
+      cleanup: Cleanup::default(),
+      tags: TagStore::default(),
+      properties: None,
+      mime: MimeDb::default(),
+      menu_type: None,
+      applications: Applications::default(),
+      output: OutputLog::default(),
+      git: Git::default(),
+      view: View::default(),
+      views: Views::default(),
+      jump: Jump::default(),
+      start_path: None,
+      ipc_sender,
+      ipc_receiver,
+      ipc_serving: false,
+      #[cfg(target_os = "linux")]
+      file_manager: None,
       settings: Settings::default(),
     }
   }
 }
 
 #[derive(Debug)]
-enum Error {
-  // dont panic
+pub enum Error {
+  /// A filesystem call on `path` failed.
+  Io {
+    action: &'static str,
+    path: PathBuf,
+    source: std::io::Error,
+  },
+  Watch {
+    path: PathBuf,
+    source: notify::Error,
+  },
+  /// Moving `path` to the system trash failed.
+  Trash {
+    path: PathBuf,
+    message: String,
+  },
+  /// An edit was rejected before anything on disk was touched.
+  Invalid(String),
+  /// Talking to the session bus failed.
+  Bus(String),
+  /// The tag database could not be read or written.
+  Tags(String),
+  /// Loading, building or saving the filesystem index failed.
+  Index(String),
+  /// Reading the status of a git repository failed.
+  Git(String),
+  /// The per-directory view database could not be read or written.
+  Views(String),
+  /// The visited directories for the jump dialog could not be saved.
+  Jump(String),
+  /// A background thread went away while we were still talking to it.
+  Disconnected(&'static str),
+}
+
+impl Error {
+  pub fn io(action: &'static str, path: &Path, source: std::io::Error) -> Self {
+    Error::Io {
+      action,
+      path: path.to_path_buf(),
+      source,
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Io {
+        action,
+        path,
+        source,
+      } => write!(f, "Could not {} {}: {}", action, path.display(), source),
+      Error::Watch { path, source } => write!(f, "Could not watch {}: {}", path.display(), source),
+      Error::Trash { path, message } => {
+        write!(f, "Could not move {} to the trash: {}", path.display(), message)
+      }
+      Error::Invalid(message) => write!(f, "{}", message),
+      Error::Bus(message) => write!(f, "Session bus: {}", message),
+      Error::Tags(message) => write!(f, "Tag database: {}", message),
+      Error::Index(message) => write!(f, "Filesystem index: {}", message),
+      Error::Git(message) => write!(f, "Git: {}", message),
+      Error::Views(message) => write!(f, "View database: {}", message),
+      Error::Jump(message) => write!(f, "Jump history: {}", message),
+      Error::Disconnected(what) => write!(f, "The {} thread stopped responding", what),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+pub struct LoggedError {
+  pub message: String,
+  pub time: SystemTime,
+  pub shown_at: Instant,
+}
+
+/// Everything that went wrong this session, newest last. Background threads
+/// report through `sender`, the UI drains `receiver` every frame.
+pub struct ErrorLog {
+  pub entries: Vec<LoggedError>,
+  pub open: bool,
+  pub sender: crossbeam_channel::Sender<Error>,
+  pub receiver: crossbeam_channel::Receiver<Error>,
+}
+
+impl Default for ErrorLog {
+  fn default() -> Self {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    Self {
+      entries: Vec::new(),
+      open: false,
+      sender,
+      receiver,
+    }
+  }
+}
+
+impl ErrorLog {
+  pub fn report(&mut self, error: Error) {
+    eprintln!("themis: {}", error);
+    self.entries.push(LoggedError {
+      message: error.to_string(),
+      time: SystemTime::now(),
+      shown_at: Instant::now(),
+    });
+  }
+
+  pub fn poll(&mut self) {
+    while let Ok(error) = self.receiver.try_recv() {
+      self.report(error);
+    }
+  }
 }
 
 #[derive(PartialEq)]
 pub enum PanelOpen {
   Main,
   Settings,
+  DiskUsage,
+  Duplicates,
+  Cleanup,
 }
 
 pub struct Rename {
   pub value: String,
   pub target: Option<std::path::PathBuf>,
+  /// The exact name being renamed, `value` starts out as its escaped form.
+  pub original: OsString,
 }
 
 impl Default for Rename {
@@ -95,6 +326,7 @@ impl Default for Rename {
     Self {
       value: "".to_owned(),
       target: None,
+      original: OsString::new(),
     }
   }
 }
@@ -102,39 +334,62 @@ impl Default for Rename {
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct DirEntry {
   pub path: std::path::PathBuf,
-  pub name: String,
+  /// The exact name on disk, use `display_name` to show it.
+  pub name: OsString,
   pub size: u64,
   pub is_dir: bool,
   pub is_empty: bool,
+  /// Recursive totals, only known for directories once the index is loaded.
+  pub stats: Option<DirStats>,
+  pub tags: Vec<String>,
+  /// Detected again on every listing, so never saved.
+  #[serde(skip)]
+  pub file_type: FileType,
+  /// Set inside a git worktree, see `misc::git`.
+  #[serde(skip)]
+  pub git: Option<GitStatus>,
 }
 impl Default for DirEntry {
   fn default() -> Self {
+    let path = current_dir().unwrap_or_default();
     Self {
-      path: current_dir().unwrap(),
-      name: current_dir()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .split('/')
-        .last()
-        .unwrap()
-        .to_owned(),
+      name: path.file_name().unwrap_or_default().to_os_string(),
+      path,
       size: 0,
       is_dir: false,
       is_empty: false,
+      stats: None,
+      tags: Vec::new(),
+      file_type: FileType::default(),
+      git: None,
     }
   }
 }
 
+impl DirEntry {
+  pub fn display_name(&self) -> String {
+    name::escape(&self.name)
+  }
+}
+
 pub struct DirWatcher {
   pub dir_watcher: crossbeam_channel::Receiver<Event>,
   pub watcher_updater: crossbeam_channel::Sender<(DirWatcherEvent, PathBuf)>,
+  /// Events waiting for the debounce window to pass, see `misc::watch`.
+  pub pending: Vec<Event>,
+  pub first_event: Option<Instant>,
+  pub last_event: Option<Instant>,
+  pub handle: Option<thread::JoinHandle<()>>,
 }
 impl Default for DirWatcher {
   fn default() -> Self {
     Self {
       dir_watcher: crossbeam_channel::unbounded().1,
       watcher_updater: crossbeam_channel::unbounded().0,
+      pending: Vec::new(),
+      first_event: None,
+      last_event: None,
+      handle: None,
     }
   }
 }
@@ -154,57 +409,64 @@ impl epi::App for Themis {
     _frame: &epi::Frame,
     storage: Option<&dyn epi::Storage>,
   ) {
-    setup_custom_fonts(&ctx);
+    setup_custom_fonts(ctx);
     // Load previous app state (if any).
     // Note that you must enable the `persistence` feature for this to work.
+    let start_path = self.start_path.take();
     #[cfg(feature = "persistence")]
     if let Some(storage) = storage {
       *self = epi::get_value(storage, epi::APP_KEY).unwrap_or_default()
     }
+    if let Some(start_path) = start_path {
+      ipc::apply(self, ipc::Command::for_path(&start_path));
+    }
+    self.ipc_serving = ipc::serve(
+      ctx.clone(),
+      self.ipc_sender.clone(),
+      self.errors.sender.clone(),
+    );
+    #[cfg(target_os = "linux")]
+    if self.settings.file_manager_service {
+      match misc::file_manager::start(ctx.clone(), self.ipc_sender.clone()) {
+        Ok(connection) => self.file_manager = Some(connection),
+        Err(err) => self.errors.report(err),
+      }
+    }
 
+    for path in std::mem::take(&mut self.pinned_dirs) {
+      self.bookmarks.add(path);
+    }
     self.drive_list = mft_ntfs::get_drive_list();
+    self.places = places::user_places();
+    self.devices_receiver = places::watch_devices(ctx.clone());
+    self.mime = MimeDb::load();
+    self.applications = Applications::load();
 
-    let (tx, rx) = crossbeam_channel::unbounded();
-
-    let (sender, receiver) = crossbeam_channel::unbounded();
-
-    self.dir_watcher.dir_watcher = receiver;
-
-    let (watcher_updater, watcher_receiver) = crossbeam_channel::unbounded();
+    match TagStore::open(&self.settings.save_load.location) {
+      Ok(tags) => self.tags = tags,
+      Err(err) => self.errors.report(err),
+    }
+    match Jump::load(&self.settings.save_load.location) {
+      Ok(jump) => self.jump = jump,
+      Err(err) => self.errors.report(err),
+    }
+    match Views::open(&self.settings.save_load.location) {
+      Ok(views) => self.views = views,
+      Err(err) => self.errors.report(err),
+    }
+    let current_path = self.current_path.clone();
+    if let Err(err) = views::load(self, &current_path) {
+      self.errors.report(err);
+    }
 
+    let (dir_watcher, watcher_updater, handle) = misc::watch::spawn(
+      self.current_path.clone(),
+      ctx.clone(),
+      self.errors.sender.clone(),
+    );
+    self.dir_watcher.dir_watcher = dir_watcher;
     self.dir_watcher.watcher_updater = watcher_updater;
-
-    let path = self.current_path.clone();
-
-    thread::spawn(move || {
-      let mut watcher = notify::recommended_watcher(move |res| match res {
-        Ok(event) => {
-          tx.send(event).unwrap();
-        }
-        Err(e) => println!("watch error: {:?}", e),
-      })
-      .unwrap();
-      watcher.watch(&path, RecursiveMode::Recursive).unwrap();
-      loop {
-        match rx.try_recv() {
-          Ok(event) => {
-            sender.send(event).unwrap();
-          }
-          Err(_) => {}
-        }
-        match watcher_receiver.try_recv() {
-          Ok(event) => match event.0 {
-            DirWatcherEvent::Add => {
-              watcher.watch(&event.1, RecursiveMode::Recursive).unwrap();
-            }
-            DirWatcherEvent::Remove => {
-              watcher.unwatch(&event.1).unwrap();
-            }
-          },
-          Err(_) => {}
-        }
-      }
-    });
+    self.dir_watcher.handle = Some(handle);
 
     let (sender, receiver) = crossbeam_channel::unbounded();
     self.fs_receiver = receiver;
@@ -213,31 +475,44 @@ impl epi::App for Themis {
 
     let load_path = self.settings.save_load.location.clone().join("filesystem.bin");
     let save_path = load_path.clone();
+    let load_errors = self.errors.sender.clone();
+    let save_errors = self.errors.sender.clone();
+    let search_settings = self.settings.search.clone();
 
     thread::spawn(move || {
-      let unserialised = std::fs::read(load_path).unwrap();
-      let filesystem = bincode::deserialize::<mft_ntfs::Filesystem>(&unserialised).unwrap();
-      load_sender.send(filesystem).unwrap();
+      match index::load(&load_path) {
+        Ok(Some(filesystem)) => {
+          let dir_sizes = DirSizes::build(&filesystem);
+          let _ = load_sender.send((filesystem, dir_sizes));
+        }
+        // * No saved index yet, the fresh scan below will create one
+        Ok(None) => {}
+        Err(err) => {
+          let _ = load_errors.send(err);
+        }
+      }
     });
 
     thread::spawn(move || {
-      let val = mft_ntfs::main(None);
-      let val = match val {
+      let mut val = match index::build() {
         Ok(val) => val,
         Err(err) => {
-          println!("{:?}", err);
+          let _ = save_errors.send(err);
           return;
         }
       };
-      let serialised = bincode::serialize(&val).unwrap();
-      // save serialised data to file
-      let mut file = std::fs::File::create(save_path).unwrap();
-      std::io::Write::write_all(&mut file, &serialised).unwrap();
+      index::exclude(&mut val, &search_settings);
+      if let Err(err) = index::save(&save_path, &val) {
+        let _ = save_errors.send(err);
+      }
 
-      sender.send(val).unwrap();
+      let dir_sizes = DirSizes::build(&val);
+      let _ = sender.send((val, dir_sizes));
     });
 
-    misc::search::update_current_dir(self);
+    if let Err(err) = misc::search::update_current_dir(self) {
+      self.errors.report(err);
+    }
   }
 
   /// Called by the frame work to save state before shutdown.
@@ -245,6 +520,9 @@ impl epi::App for Themis {
   #[cfg(feature = "persistence")]
   fn save(&mut self, storage: &mut dyn epi::Storage) {
     epi::set_value(storage, epi::APP_KEY, self);
+    if let Err(err) = self.jump.save() {
+      self.errors.report(err);
+    }
     // * Don't save the fs when the app is being closed
     // * It takes +-15 seconds
     // let serialised = bincode::serialize(&self.filesystem).unwrap();
@@ -252,6 +530,25 @@ impl epi::App for Themis {
     // std::io::Write::write_all(&mut file, &serialised).unwrap();
   }
 
+  /// Called once on shutdown, after `save`.
+  fn on_exit(&mut self) {
+    if let Err(err) = self.jump.save() {
+      eprintln!("themis: {}", err);
+    }
+    let current_path = self.current_path.clone();
+    if let Err(err) = views::save(self, &current_path) {
+      eprintln!("themis: {}", err);
+    }
+    if self.ipc_serving {
+      ipc::stop();
+    }
+    // * Dropping the only request sender is what stops the watcher thread
+    self.dir_watcher.watcher_updater = crossbeam_channel::unbounded().0;
+    if let Some(handle) = self.dir_watcher.handle.take() {
+      let _ = handle.join();
+    }
+  }
+
   /// Called each time the UI needs repainting, which may be many times per second.
   /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
   fn update(&mut self, ctx: &egui::Context, _frame: &epi::Frame) {
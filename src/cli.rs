@@ -0,0 +1,361 @@
+use bytesize::ByteSize;
+use std::env::current_dir;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::app::Error;
+use crate::misc::dir_sizes::DirSizes;
+use crate::misc::disk_usage::{self, UsageItem};
+use crate::misc::excludes::Excludes;
+use crate::misc::index;
+use crate::misc::name::escape;
+use crate::misc::search::Matcher;
+use crate::ui::settings::{MatchMode, SearchMode, SearchSettings};
+
+const USAGE: &str = "Usage:
+  themis                      open the file manager
+  themis <path>               open <path>, in the running window if there is one
+  themis open <dir>           open the file manager at <dir>
+  themis index [<root>]       build the index, or bring the part below <root> up to date
+  themis search <query> [--regex|--glob|--contains|--fuzzy] [--in <dir>] [--json]
+                              [--no-hidden]
+  themis du [<dir>]           print the sizes of everything in <dir>
+
+Options:
+  --index <file>              index file to use, the window's filesystem.bin by default
+  --respect-ignore            leave out what .gitignore, .ignore and git's global excludes ignore
+  --exclude <glob>            leave out names matching <glob>, can be repeated";
+
+pub enum Outcome {
+  /// Start the window, optionally at a given directory.
+  Gui(Option<PathBuf>),
+  Exit(i32),
+}
+
+/// Handles the subcommands, leaving everything else to the window.
+pub fn run(args: &[OsString]) -> Outcome {
+  let command = match args.first() {
+    Some(command) => command.to_string_lossy().into_owned(),
+    None => return Outcome::Gui(None),
+  };
+  let options = match Options::parse(&args[1..]) {
+    Ok(options) => options,
+    Err(message) => return usage_error(&message),
+  };
+  let result = match command.as_str() {
+    "open" => {
+      let dir = options.positional.first().cloned().map(PathBuf::from);
+      return match dir {
+        Some(dir) if !dir.is_dir() => {
+          usage_error(&format!("{} is not a directory", dir.display()))
+        }
+        dir => Outcome::Gui(dir),
+      };
+    }
+    "index" => index(&options),
+    "search" => search(&options),
+    "du" => du(&options),
+    "help" | "--help" | "-h" => {
+      println!("{}", USAGE);
+      return Outcome::Exit(0);
+    }
+    // * `themis <path>` opens it like `open`, revealing files in their folder
+    _ if Path::new(&args[0]).exists() => return Outcome::Gui(Some(PathBuf::from(&args[0]))),
+    other => return usage_error(&format!("unknown command {}", other)),
+  };
+  match result {
+    Ok(()) => Outcome::Exit(0),
+    Err(err) => {
+      eprintln!("themis: {}", err);
+      Outcome::Exit(1)
+    }
+  }
+}
+
+fn usage_error(message: &str) -> Outcome {
+  eprintln!("themis: {}\n\n{}", message, USAGE);
+  Outcome::Exit(2)
+}
+
+struct Options {
+  positional: Vec<OsString>,
+  index: PathBuf,
+  mode: SearchMode,
+  within: PathBuf,
+  json: bool,
+  /// Filled in from `--respect-ignore`, `--exclude` and `--no-hidden`.
+  excludes: SearchSettings,
+}
+
+impl Options {
+  fn parse(args: &[OsString]) -> Result<Self, String> {
+    let mut options = Self {
+      positional: Vec::new(),
+      index: saved_location()
+        .unwrap_or_else(|| current_dir().unwrap_or_default())
+        .join("filesystem.bin"),
+      mode: SearchMode::Regex,
+      within: PathBuf::new(),
+      json: false,
+      excludes: SearchSettings::default(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      let mut value = |name: &str| {
+        args
+          .next()
+          .map(PathBuf::from)
+          .ok_or_else(|| format!("{} needs a value", name))
+      };
+      match arg.to_str() {
+        Some("--index") => options.index = value("--index")?,
+        Some("--in") => options.within = value("--in")?,
+        Some("--regex") => options.mode = SearchMode::Regex,
+        Some("--glob") => options.mode = SearchMode::Glob,
+        Some("--contains") => options.mode = SearchMode::Contains,
+        Some("--fuzzy") => options.mode = SearchMode::Fuzzy,
+        Some("--json") => options.json = true,
+        Some("--respect-ignore") => options.excludes.respect_ignore = true,
+        Some("--no-hidden") => options.excludes.show_hidden = false,
+        Some("--exclude") => {
+          let glob = value("--exclude")?;
+          options.excludes.exclude_globs.push(glob.to_string_lossy().into_owned());
+        }
+        Some(flag) if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+        _ => options.positional.push(arg.clone()),
+      }
+    }
+    Ok(options)
+  }
+}
+
+/// Builds the index. With a root, the saved index is brought up to date
+/// below it from the disk, and only when that finds new entries is a fresh
+/// scan used for that part.
+fn index(options: &Options) -> Result<(), Error> {
+  let root = options.positional.first().map(PathBuf::from);
+  let mut saved = match &root {
+    Some(_) => index::load(&options.index)?,
+    None => None,
+  };
+  let refreshed = match (&root, &mut saved) {
+    (Some(root), Some(saved)) => index::refresh(saved, root, &options.excludes),
+    _ => None,
+  };
+  if let Some(left_out) = refreshed.filter(|left_out| *left_out > 0) {
+    eprintln!(
+      "Left out {} paths that aren't valid UTF-8, search won't find them",
+      left_out
+    );
+  }
+  let filesystem = match saved {
+    Some(saved) if refreshed.is_some() => saved,
+    saved => {
+      let mut filesystem = index::build()?;
+      index::exclude(&mut filesystem, &options.excludes);
+      if let Some(root) = &root {
+        filesystem.files.retain(|key, _| Path::new(key).starts_with(root));
+        if let Some(mut saved) = saved {
+          saved.files.retain(|key, _| !Path::new(key).starts_with(root));
+          filesystem.files.extend(saved.files);
+        }
+      }
+      filesystem
+    }
+  };
+  index::save(&options.index, &filesystem)?;
+  println!(
+    "Indexed {} entries into {}",
+    filesystem.files.len(),
+    options.index.display()
+  );
+  Ok(())
+}
+
+fn search(options: &Options) -> Result<(), Error> {
+  let query = match options.positional.first() {
+    Some(query) => query.to_string_lossy().into_owned(),
+    None => return Err(Error::Invalid("search needs a query".to_owned())),
+  };
+  let filesystem = load(options)?;
+  let settings = SearchSettings {
+    search_mode: options.mode.clone(),
+    recursive: true,
+    match_mode: MatchMode::Normal,
+    ..options.excludes.clone()
+  };
+  let matcher = Matcher::new(&query, &options.within, &settings);
+  let mut excludes = Excludes::new(&options.within, &settings);
+  // * Only needed to tell directories apart for the ignore rules
+  let dir_sizes = if excludes.is_active() {
+    DirSizes::build(&filesystem)
+  } else {
+    DirSizes::default()
+  };
+  let mut results: Vec<(&String, u64)> = filesystem
+    .files
+    .iter()
+    .filter(|(key, _)| matcher.matches(Path::new(key.as_str())))
+    .filter(|(key, _)| {
+      let path = Path::new(key.as_str());
+      !excludes.is_excluded(path, dir_sizes.dirs.contains_key(path))
+    })
+    .map(|(key, file)| (key, file.real_size))
+    .collect();
+  results.sort();
+  print!("{}", search_output(&results, options.json));
+  Ok(())
+}
+
+/// One path per line, or a JSON array of paths and sizes.
+fn search_output(results: &[(&String, u64)], json: bool) -> String {
+  if json {
+    let results: Vec<_> = results
+      .iter()
+      .map(|(path, size)| serde_json::json!({ "path": path, "size": size }))
+      .collect();
+    format!("{}\n", serde_json::Value::Array(results))
+  } else {
+    results.iter().map(|(path, _)| format!("{}\n", path)).collect()
+  }
+}
+
+/// Prints the children of a directory with their total sizes, from the
+/// index when there is one and from the disk otherwise.
+fn du(options: &Options) -> Result<(), Error> {
+  let dir = match options.positional.first() {
+    Some(dir) => PathBuf::from(dir),
+    None => current_dir().map_err(|err| Error::io("find", Path::new("."), err))?,
+  };
+  let mut items: Vec<UsageItem> = match index::load(&options.index)? {
+    Some(filesystem) => {
+      let dir_sizes = DirSizes::build(&filesystem);
+      disk_usage::from_index(&filesystem, &dir_sizes, &dir)
+    }
+    None => disk_usage::scan(&dir),
+  };
+  print!("{}", du_output(&mut items, &dir));
+  Ok(())
+}
+
+/// Biggest first, directories marked with a `/`, then the total.
+fn du_output(items: &mut [UsageItem], dir: &Path) -> String {
+  items.sort_by_key(|item| std::cmp::Reverse(item.size));
+  let total: u64 = items.iter().map(|item| item.size).sum();
+  let mut output = String::new();
+  for item in items.iter() {
+    let suffix = if item.is_dir { "/" } else { "" };
+    let size = ByteSize(item.size).to_string();
+    output += &format!("{:>10}  {}{}\n", size, escape(&item.name), suffix);
+  }
+  output += &format!("{:>10}  {}\n", ByteSize(total).to_string(), escape(dir.as_os_str()));
+  output
+}
+
+/// Where the window keeps its files, from its saved settings.
+#[cfg(feature = "persistence")]
+fn saved_location() -> Option<PathBuf> {
+  #[derive(serde::Deserialize)]
+  struct Saved {
+    settings: crate::ui::settings::Settings,
+  }
+  let storage = eframe::epi::file_storage::FileStorage::from_app_name("themis")?;
+  let saved: Saved = eframe::epi::get_value(&storage, eframe::epi::APP_KEY)?;
+  Some(saved.settings.save_load.location)
+}
+
+#[cfg(not(feature = "persistence"))]
+fn saved_location() -> Option<PathBuf> {
+  None
+}
+
+fn load(options: &Options) -> Result<mft_ntfs::Filesystem, Error> {
+  index::load(&options.index)?.ok_or_else(|| {
+    Error::Invalid(format!(
+      "No index at {}, run `themis index` first",
+      options.index.display()
+    ))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  fn parse(args: &[&str]) -> Result<Options, String> {
+    let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+    Options::parse(&args)
+  }
+
+  #[test]
+  fn options_are_read_around_the_query() {
+    let options = parse(&[
+      "--glob",
+      "*.rs",
+      "--in",
+      "/src",
+      "--json",
+      "--exclude",
+      "target",
+      "--exclude",
+      "*.o",
+      "--respect-ignore",
+      "--no-hidden",
+      "--index",
+      "/tmp/files.bin",
+    ])
+    .unwrap();
+    assert_eq!(options.positional, vec![OsString::from("*.rs")]);
+    assert!(options.mode == SearchMode::Glob);
+    assert_eq!(options.within, PathBuf::from("/src"));
+    assert!(options.json);
+    assert_eq!(options.excludes.exclude_globs, vec!["target", "*.o"]);
+    assert!(options.excludes.respect_ignore);
+    assert!(!options.excludes.show_hidden);
+    assert_eq!(options.index, PathBuf::from("/tmp/files.bin"));
+
+    let options = parse(&["invoice"]).unwrap();
+    assert!(options.mode == SearchMode::Regex);
+    assert!(!options.json);
+    assert_eq!(options.index.file_name().unwrap(), "filesystem.bin");
+  }
+
+  #[test]
+  fn bad_options_are_refused() {
+    assert_eq!(parse(&["x", "--in"]).err().unwrap(), "--in needs a value");
+    assert_eq!(parse(&["--recursive"]).err().unwrap(), "unknown option --recursive");
+  }
+
+  #[test]
+  fn search_prints_paths_or_json() {
+    let (a, b) = (String::from("/home/me/a.txt"), String::from("/home/me/b.txt"));
+    let results = [(&a, 3), (&b, 10)];
+    assert_eq!(search_output(&results, false), "/home/me/a.txt\n/home/me/b.txt\n");
+    let json: serde_json::Value = serde_json::from_str(&search_output(&results, true)).unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!([
+        { "path": "/home/me/a.txt", "size": 3 },
+        { "path": "/home/me/b.txt", "size": 10 },
+      ])
+    );
+    assert_eq!(search_output(&[], true), "[]\n");
+  }
+
+  #[test]
+  fn du_lists_the_biggest_first_then_the_total() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    std::fs::create_dir(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src").join("main.rs"), "fn main() {}").unwrap();
+    std::fs::write(dir.join("notes"), "abc").unwrap();
+    let mut items = disk_usage::scan(dir);
+    let output = du_output(&mut items, dir);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], format!("{:>10}  src/", "12 B"));
+    assert_eq!(lines[1], format!("{:>10}  notes", "3 B"));
+    assert_eq!(lines[2], format!("{:>10}  {}", "15 B", dir.display()));
+  }
+}
@@ -3,6 +3,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 pub mod app;
+pub mod cli;
 pub mod ui;
 pub mod misc;
 use app::Themis;
@@ -10,7 +11,21 @@ use app::Themis;
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
-  let app = Themis::default();
+  let args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).collect();
+  let start_path = match cli::run(&args) {
+    cli::Outcome::Gui(start_path) => start_path,
+    cli::Outcome::Exit(code) => std::process::exit(code),
+  };
+  // * Hand the path to a window that is already open, if there is one
+  if let Some(path) = &start_path {
+    if misc::ipc::forward(&misc::ipc::Command::for_path(path)) {
+      return;
+    }
+  }
+  let app = Themis {
+    start_path,
+    ..Themis::default()
+  };
   let native_options = eframe::NativeOptions::default();
   eframe::run_native(Box::new(app), native_options);
 }
@@ -0,0 +1,119 @@
+use eframe::egui;
+use glob::Pattern;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::app::{Error, Themis};
+use crate::misc::output::{self, quote, shell};
+use crate::ui::settings::CustomAction;
+
+impl CustomAction {
+  /// Whether the action shows up for `path`. The condition is a list of
+  /// globs, those with a `/` match the MIME type and the rest the name.
+  pub fn applies_to(&self, path: &Path, mime: &str) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut conditions = self.condition.split_whitespace().peekable();
+    conditions.peek().is_none()
+      || conditions.any(|condition| match Pattern::new(condition) {
+        Ok(pattern) if condition.contains('/') => pattern.matches(mime),
+        Ok(pattern) => pattern.matches(&name),
+        Err(_) => false,
+      })
+  }
+}
+
+/// Runs `action` on `paths` from a shell in the directory of the first one.
+pub fn run(
+  state: &mut Themis,
+  ctx: &egui::Context,
+  action: &CustomAction,
+  paths: &[PathBuf],
+) -> Result<(), Error> {
+  let first = match paths.first() {
+    Some(first) => first,
+    None => return Err(Error::Invalid(format!("{} needs a file to run on", action.name))),
+  };
+  let dir = first.parent().unwrap_or(first).to_path_buf();
+  let script = expand(&action.command, paths);
+  let mut command = if action.terminal {
+    let terminal = env::var("TERMINAL").unwrap_or_else(|_| "xterm".to_owned());
+    let mut command = Command::new(terminal);
+    command.arg("-e").args(shell(&script));
+    command
+  } else {
+    let shell = shell(&script);
+    let mut command = Command::new(&shell[0]);
+    command.args(&shell[1..]);
+    command
+  };
+  command.env("THEMIS_DIR", &dir);
+  let show = action.capture_output && !action.terminal;
+  output::spawn(state, ctx, action.name.clone(), command, show, action.refresh, &dir)
+}
+
+/// Fills in `{path}`, `{paths}`, `{dir}`, `{name}` and `{stem}`, quoted for
+/// the shell with the bytes of the paths left as they are.
+pub fn expand(template: &str, paths: &[PathBuf]) -> OsString {
+  let first = paths.first().cloned().unwrap_or_default();
+  let mut script = OsString::new();
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    script.push(&rest[..start]);
+    rest = &rest[start..];
+    let placeholder = PLACEHOLDERS
+      .iter()
+      .find(|placeholder| rest.starts_with(**placeholder));
+    match placeholder {
+      Some(&"{paths}") => {
+        for (index, path) in paths.iter().enumerate() {
+          if index > 0 {
+            script.push(" ");
+          }
+          script.push(quote(path.as_os_str()));
+        }
+      }
+      Some(&"{path}") => script.push(quote(first.as_os_str())),
+      Some(&"{dir}") => script.push(quote(first.parent().unwrap_or(&first).as_os_str())),
+      Some(&"{name}") => script.push(quote(first.file_name().unwrap_or_default())),
+      Some(&"{stem}") => script.push(quote(first.file_stem().unwrap_or_default())),
+      _ => {
+        script.push("{");
+        rest = &rest[1..];
+        continue;
+      }
+    }
+    rest = &rest[placeholder.map_or(0, |placeholder| placeholder.len())..];
+  }
+  script.push(rest);
+  script
+}
+
+const PLACEHOLDERS: [&str; 5] = ["{paths}", "{path}", "{dir}", "{name}", "{stem}"];
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::ffi::OsStr;
+  use std::os::unix::ffi::OsStrExt;
+
+  #[test]
+  fn placeholders_are_quoted() {
+    let paths = vec![PathBuf::from("/src/my file.rs"), PathBuf::from("/src/lib.rs")];
+    assert_eq!(
+      expand("wc {paths} > {dir}/{stem}.txt", &paths),
+      OsStr::new("wc '/src/my file.rs' /src/lib.rs > /src/'my file'.txt")
+    );
+    assert_eq!(expand("echo {name} {unknown}", &paths), OsStr::new("echo 'my file.rs' {unknown}"));
+  }
+
+  #[test]
+  fn paths_keep_their_bytes() {
+    let paths = vec![PathBuf::from(OsStr::from_bytes(b"/tmp/it's\xE9"))];
+    assert_eq!(
+      expand("cat {path}", &paths).as_bytes(),
+      b"cat '/tmp/it'\\''s\xE9'"
+    );
+  }
+}
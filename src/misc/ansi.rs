@@ -0,0 +1,156 @@
+use eframe::egui::text::{LayoutJob, TextFormat};
+use eframe::egui::{Color32, FontId};
+
+const COLORS: [Color32; 8] = [
+  Color32::from_rgb(60, 60, 60),
+  Color32::from_rgb(205, 80, 80),
+  Color32::from_rgb(110, 190, 90),
+  Color32::from_rgb(215, 185, 80),
+  Color32::from_rgb(90, 140, 220),
+  Color32::from_rgb(190, 110, 200),
+  Color32::from_rgb(80, 190, 200),
+  Color32::from_rgb(200, 200, 200),
+];
+
+const BRIGHT_COLORS: [Color32; 8] = [
+  Color32::from_rgb(128, 128, 128),
+  Color32::from_rgb(255, 110, 110),
+  Color32::from_rgb(140, 230, 120),
+  Color32::from_rgb(250, 225, 110),
+  Color32::from_rgb(130, 175, 255),
+  Color32::from_rgb(230, 140, 240),
+  Color32::from_rgb(120, 230, 240),
+  Color32::from_rgb(255, 255, 255),
+];
+
+/// Lays out terminal output, following the SGR color codes (`ESC[...m`)
+/// and dropping every other escape sequence.
+pub fn layout(text: &str, font: FontId, default: Color32) -> LayoutJob {
+  let mut job = LayoutJob::default();
+  let mut format = TextFormat::simple(font, default);
+  let mut rest = text;
+  while let Some(start) = rest.find('\u{1b}') {
+    job.append(&rest[..start], 0.0, format.clone());
+    rest = &rest[start + 1..];
+    if !rest.starts_with('[') {
+      continue;
+    }
+    // * A CSI sequence ends at the first byte in the `@`..`~` range
+    let end = match rest[1..].find(|c: char| ('@'..='~').contains(&c)) {
+      Some(end) => end + 1,
+      None => break,
+    };
+    if rest[end..].starts_with('m') {
+      apply(&rest[1..end], &mut format, default);
+    }
+    rest = &rest[end + 1..];
+  }
+  job.append(rest, 0.0, format);
+  job
+}
+
+fn apply(codes: &str, format: &mut TextFormat, default: Color32) {
+  let mut codes = codes.split(';').map(|code| code.parse::<usize>().unwrap_or(0));
+  while let Some(code) = codes.next() {
+    match code {
+      0 => {
+        format.color = default;
+        format.background = Color32::TRANSPARENT;
+        format.underline = Default::default();
+      }
+      4 => format.underline = eframe::egui::Stroke::new(1.0, format.color),
+      24 => format.underline = Default::default(),
+      code @ 30..=37 => format.color = COLORS[code - 30],
+      // * Extended colors take their arguments with them
+      38 => format.color = extended(&mut codes).unwrap_or(format.color),
+      39 => format.color = default,
+      code @ 40..=47 => format.background = COLORS[code - 40],
+      48 => format.background = extended(&mut codes).unwrap_or(format.background),
+      49 => format.background = Color32::TRANSPARENT,
+      code @ 90..=97 => format.color = BRIGHT_COLORS[code - 90],
+      code @ 100..=107 => format.background = BRIGHT_COLORS[code - 100],
+      _ => {}
+    }
+  }
+}
+
+/// The color after a 38 or 48: `5;n` from the 256 color palette or
+/// `2;r;g;b`.
+fn extended(codes: &mut impl Iterator<Item = usize>) -> Option<Color32> {
+  match codes.next()? {
+    5 => codes.next().map(palette),
+    2 => {
+      let mut channel = || codes.next().map(|value| value.min(255) as u8);
+      let (r, g, b) = (channel(), channel(), channel());
+      Some(Color32::from_rgb(r?, g?, b?))
+    }
+    _ => None,
+  }
+}
+
+/// The xterm 256 color palette: the 16 basic colors, a 6×6×6 cube and a
+/// gray ramp.
+fn palette(index: usize) -> Color32 {
+  const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+  match index {
+    0..=7 => COLORS[index],
+    8..=15 => BRIGHT_COLORS[index - 8],
+    16..=231 => {
+      let cube = index - 16;
+      Color32::from_rgb(LEVELS[cube / 36], LEVELS[cube / 6 % 6], LEVELS[cube % 6])
+    }
+    _ => {
+      let gray = (8 + 10 * (index.min(255) - 232)) as u8;
+      Color32::from_rgb(gray, gray, gray)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DEFAULT: Color32 = Color32::from_rgb(1, 2, 3);
+
+  fn format_after(codes: &str) -> TextFormat {
+    let mut format = TextFormat::simple(FontId::default(), DEFAULT);
+    apply(codes, &mut format, DEFAULT);
+    format
+  }
+
+  #[test]
+  fn basic_colors_and_reset() {
+    assert_eq!(format_after("31").color, COLORS[1]);
+    assert_eq!(format_after("1;94").color, BRIGHT_COLORS[4]);
+    assert_eq!(format_after("42").background, COLORS[2]);
+    assert_eq!(format_after("31;0").color, DEFAULT);
+    assert_eq!(format_after("").color, DEFAULT);
+  }
+
+  #[test]
+  fn extended_colors_take_their_arguments() {
+    // * Not red from the 31, nor a reset from the 0
+    assert_eq!(format_after("38;5;31").color, Color32::from_rgb(0, 135, 175));
+    assert_eq!(format_after("38;2;255;0;0").color, Color32::from_rgb(255, 0, 0));
+    assert_eq!(format_after("38;5;244").color, Color32::from_rgb(128, 128, 128));
+    let format = format_after("48;2;0;0;255;4");
+    assert_eq!(format.background, Color32::from_rgb(0, 0, 255));
+    assert_eq!(format.color, DEFAULT);
+    assert_ne!(format.underline, Default::default());
+  }
+
+  #[test]
+  fn cut_off_sequences_change_nothing() {
+    assert_eq!(format_after("38;2;255").color, DEFAULT);
+    assert_eq!(format_after("38;7;31").color, COLORS[1]);
+    assert_eq!(format_after("48;5").background, Color32::TRANSPARENT);
+  }
+
+  #[test]
+  fn layout_drops_the_escapes() {
+    let job = layout("\u{1b}[38;5;31mblue\u{1b}[0m plain\u{1b}[2K", FontId::default(), DEFAULT);
+    assert_eq!(job.text, "blue plain");
+    let blue = job.sections.iter().find(|section| section.byte_range == (0..4)).unwrap();
+    assert_eq!(blue.format.color, Color32::from_rgb(0, 135, 175));
+  }
+}
@@ -0,0 +1,278 @@
+//! Sidebar bookmarks. They can be shared with other file managers through
+//! the GTK `bookmarks` file, which only knows about paths and names.
+
+use eframe::egui;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::Error;
+use crate::misc::open_with::{path_from_uri, uri};
+
+const SHORTCUTS: [egui::Key; 9] = [
+  egui::Key::Num1,
+  egui::Key::Num2,
+  egui::Key::Num3,
+  egui::Key::Num4,
+  egui::Key::Num5,
+  egui::Key::Num6,
+  egui::Key::Num7,
+  egui::Key::Num8,
+  egui::Key::Num9,
+];
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Bookmark {
+  pub name: String,
+  pub icon: String,
+  /// Empty for bookmarks outside of any group.
+  pub group: String,
+  #[serde(with = "crate::misc::name::saved_path")]
+  pub path: PathBuf,
+}
+
+impl Bookmark {
+  pub fn new(path: PathBuf) -> Self {
+    Self {
+      name: default_name(&path),
+      icon: "🗀".to_owned(),
+      group: "".to_owned(),
+      path,
+    }
+  }
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Bookmarks {
+  pub items: Vec<Bookmark>,
+  #[serde(skip)]
+  pub dragging: Option<usize>,
+  #[serde(skip)]
+  pub editing: Option<usize>,
+}
+
+impl Bookmarks {
+  pub fn contains(&self, path: &Path) -> bool {
+    self.items.iter().any(|bookmark| bookmark.path == path)
+  }
+
+  pub fn add(&mut self, path: PathBuf) {
+    if !self.contains(&path) {
+      self.items.push(Bookmark::new(path));
+    }
+  }
+
+  pub fn remove(&mut self, path: &Path) {
+    self.items.retain(|bookmark| bookmark.path != path);
+    self.editing = None;
+  }
+
+  /// Indices in the order they are shown: groups in order of their first
+  /// bookmark, ungrouped ones first.
+  pub fn display_order(&self) -> Vec<usize> {
+    let mut groups: Vec<&str> = vec![""];
+    for bookmark in &self.items {
+      if !groups.contains(&bookmark.group.as_str()) {
+        groups.push(&bookmark.group);
+      }
+    }
+    groups
+      .iter()
+      .flat_map(|group| {
+        self
+          .items
+          .iter()
+          .enumerate()
+          .filter(move |(_, bookmark)| bookmark.group == *group)
+          .map(|(index, _)| index)
+      })
+      .collect()
+  }
+
+  /// Puts the bookmark at `from` in front of the one at `to`, taking over
+  /// its group so bookmarks can be dragged between groups.
+  pub fn move_to(&mut self, from: usize, to: usize) {
+    if from == to || from >= self.items.len() || to >= self.items.len() {
+      return;
+    }
+    let mut bookmark = self.items.remove(from);
+    let to = if from < to { to - 1 } else { to };
+    bookmark.group = self.items[to].group.clone();
+    self.items.insert(to, bookmark);
+    self.editing = None;
+  }
+
+  /// The bookmark picked with Ctrl+1 to Ctrl+9, in display order.
+  pub fn shortcut(&self, ctx: &egui::Context) -> Option<PathBuf> {
+    let order = self.display_order();
+    let mut input = ctx.input_mut();
+    let pressed = SHORTCUTS
+      .iter()
+      .position(|key| input.consume_key(egui::Modifiers::COMMAND, *key))?;
+    order.get(pressed).map(|index| self.items[*index].path.clone())
+  }
+
+  /// Adds the local bookmarks from the GTK file that we don't have yet,
+  /// returning how many there were.
+  pub fn import(&mut self) -> Result<usize, Error> {
+    let path = gtk_bookmarks();
+    let contents = fs::read_to_string(&path).map_err(|err| Error::io("read bookmarks", &path, err))?;
+    let mut added = 0;
+    for line in contents.lines().map(str::trim) {
+      let (location, name) = match line.split_once(' ') {
+        Some((location, name)) => (location, Some(name.trim())),
+        None => (line, None),
+      };
+      // * Network locations like `sftp://` can't be browsed here
+      let path = match path_from_uri(location) {
+        Some(path) if location.starts_with("file://") => path,
+        _ => continue,
+      };
+      if self.contains(&path) {
+        continue;
+      }
+      let mut bookmark = Bookmark::new(path);
+      if let Some(name) = name.filter(|name| !name.is_empty()) {
+        bookmark.name = name.to_owned();
+      }
+      self.items.push(bookmark);
+      added += 1;
+    }
+    Ok(added)
+  }
+
+  /// Writes our bookmarks to the GTK file, see `merge`.
+  pub fn export(&self) -> Result<PathBuf, Error> {
+    let path = gtk_bookmarks();
+    let existing = match fs::read_to_string(&path) {
+      Ok(contents) => contents,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+      Err(err) => return Err(Error::io("read bookmarks", &path, err)),
+    };
+    let contents = self.merge(&existing);
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|err| Error::io("create", parent, err))?;
+    }
+    fs::write(&path, contents).map_err(|err| Error::io("write bookmarks", &path, err))?;
+    Ok(path)
+  }
+
+  /// The GTK file with our bookmarks in it. Entries other file managers
+  /// added stay where they are, ours are updated in place and new ones go
+  /// at the end in display order.
+  fn merge(&self, existing: &str) -> String {
+    let line = |bookmark: &Bookmark| {
+      let mut line = uri(&bookmark.path);
+      if bookmark.name != default_name(&bookmark.path) {
+        line.push(' ');
+        line.push_str(&bookmark.name);
+      }
+      line
+    };
+    let mut written = vec![false; self.items.len()];
+    let mut contents = String::new();
+    for existing in existing.lines().filter(|existing| !existing.trim().is_empty()) {
+      let location = existing.split(' ').next().unwrap_or_default();
+      let ours = match path_from_uri(location) {
+        Some(path) if location.starts_with("file://") => {
+          self.items.iter().position(|bookmark| bookmark.path == path)
+        }
+        _ => None,
+      };
+      match ours {
+        Some(index) if !written[index] => {
+          written[index] = true;
+          contents.push_str(&line(&self.items[index]));
+        }
+        Some(_) => continue,
+        None => contents.push_str(existing),
+      }
+      contents.push('\n');
+    }
+    for index in self.display_order() {
+      if !written[index] {
+        contents.push_str(&line(&self.items[index]));
+        contents.push('\n');
+      }
+    }
+    contents
+  }
+}
+
+/// The GTK 3 bookmarks file, also read by GTK 4 apps.
+fn gtk_bookmarks() -> PathBuf {
+  let config = match env::var_os("XDG_CONFIG_HOME") {
+    Some(config) if !config.is_empty() => PathBuf::from(config),
+    _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"),
+  };
+  config.join("gtk-3.0").join("bookmarks")
+}
+
+fn default_name(path: &Path) -> String {
+  match path.file_name() {
+    Some(name) => name.to_string_lossy().into_owned(),
+    None => path.to_string_lossy().into_owned(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bookmarks(items: &[(&str, &str)]) -> Bookmarks {
+    let items = items
+      .iter()
+      .map(|(path, group)| Bookmark {
+        group: group.to_string(),
+        ..Bookmark::new(PathBuf::from(path))
+      })
+      .collect();
+    Bookmarks {
+      items,
+      ..Bookmarks::default()
+    }
+  }
+
+  fn paths(bookmarks: &Bookmarks) -> Vec<&str> {
+    bookmarks.items.iter().map(|bookmark| bookmark.path.to_str().unwrap()).collect()
+  }
+
+  #[test]
+  fn ungrouped_come_first_then_groups_in_order() {
+    let bookmarks = bookmarks(&[("/a", "work"), ("/b", ""), ("/c", "music"), ("/d", "work")]);
+    assert_eq!(bookmarks.display_order(), vec![1, 0, 3, 2]);
+  }
+
+  #[test]
+  fn moving_takes_the_group_of_the_target() {
+    let mut bookmarks = bookmarks(&[("/a", ""), ("/b", "work"), ("/c", "work")]);
+    bookmarks.move_to(0, 2);
+    assert_eq!(paths(&bookmarks), vec!["/b", "/a", "/c"]);
+    assert_eq!(bookmarks.items[1].group, "work");
+    bookmarks.move_to(2, 0);
+    assert_eq!(paths(&bookmarks), vec!["/c", "/b", "/a"]);
+    assert_eq!(bookmarks.display_order(), vec![0, 1, 2]);
+  }
+
+  #[test]
+  fn moving_out_of_range_does_nothing() {
+    let mut bookmarks = bookmarks(&[("/a", ""), ("/b", "")]);
+    bookmarks.editing = Some(1);
+    bookmarks.move_to(1, 2);
+    bookmarks.move_to(1, 1);
+    assert_eq!(paths(&bookmarks), vec!["/a", "/b"]);
+    assert_eq!(bookmarks.editing, Some(1));
+  }
+
+  #[test]
+  fn export_keeps_other_bookmarks_in_place() {
+    let mut bookmarks = bookmarks(&[("/home/me/code", ""), ("/home/me/new", "")]);
+    bookmarks.items[0].name = "Code".to_owned();
+    let existing = "file:///home/me/Music\nsftp://server/home Server\nfile:///home/me/code\nfile:///home/me/Films Films\n";
+    assert_eq!(
+      bookmarks.merge(existing),
+      "file:///home/me/Music\nsftp://server/home Server\nfile:///home/me/code Code\nfile:///home/me/Films Films\nfile:///home/me/new\n"
+    );
+  }
+}
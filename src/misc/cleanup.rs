@@ -0,0 +1,311 @@
+use crossbeam_channel::Receiver;
+use glob::Pattern;
+use std::fmt::Write as _;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::app::{Error, Themis};
+use crate::misc::search::update_current_dir;
+use crate::ui::settings::CleanupSettings;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Category {
+  EmptyDir,
+  BrokenSymlink,
+  ZeroByte,
+  StaleTemp,
+}
+
+impl Category {
+  pub const ALL: [Category; 4] = [
+    Category::EmptyDir,
+    Category::BrokenSymlink,
+    Category::ZeroByte,
+    Category::StaleTemp,
+  ];
+
+  pub fn label(self) -> &'static str {
+    match self {
+      Category::EmptyDir => "Empty folders",
+      Category::BrokenSymlink => "Broken symlinks",
+      Category::ZeroByte => "Zero-byte files",
+      Category::StaleTemp => "Stale temporary files",
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct Finding {
+  pub path: PathBuf,
+  pub category: Category,
+  pub size: u64,
+}
+
+#[derive(Default)]
+pub struct Cleanup {
+  pub root: Option<PathBuf>,
+  pub findings: Vec<Finding>,
+  /// Set while a background scan is running for `root`.
+  pub scan: Option<Receiver<Vec<Finding>>>,
+  /// Where the last report was exported to.
+  pub exported: Option<PathBuf>,
+}
+
+impl Cleanup {
+  pub fn of(&self, category: Category) -> impl Iterator<Item = &Finding> {
+    self
+      .findings
+      .iter()
+      .filter(move |finding| finding.category == category)
+  }
+}
+
+/// What counts as a stale temporary file.
+struct Rules {
+  patterns: Vec<Pattern>,
+  cutoff: SystemTime,
+}
+
+impl Rules {
+  fn new(settings: &CleanupSettings, now: SystemTime) -> Self {
+    Self {
+      // * Invalid patterns are flagged in the settings panel, skip them here
+      patterns: settings
+        .temp_globs
+        .iter()
+        .filter_map(|glob| Pattern::new(glob).ok())
+        .collect(),
+      cutoff: now
+        .checked_sub(Duration::from_secs(settings.stale_days.saturating_mul(24 * 60 * 60)))
+        .unwrap_or(SystemTime::UNIX_EPOCH),
+    }
+  }
+
+  fn is_stale(&self, name: &str, metadata: &Metadata) -> bool {
+    self.patterns.iter().any(|pattern| pattern.matches(name))
+      && matches!(metadata.modified(), Ok(modified) if modified < self.cutoff)
+  }
+}
+
+/// Scans `root` in the background, see `poll` for the results.
+pub fn scan(state: &mut Themis, root: PathBuf) {
+  let rules = Rules::new(&state.settings.cleanup, SystemTime::now());
+  let (sender, receiver) = crossbeam_channel::bounded(1);
+  state.cleanup.root = Some(root.clone());
+  state.cleanup.findings = Vec::new();
+  state.cleanup.exported = None;
+  state.cleanup.scan = Some(receiver);
+  thread::spawn(move || {
+    let _ = sender.send(find(&root, &rules));
+  });
+}
+
+fn find(root: &Path, rules: &Rules) -> Vec<Finding> {
+  let mut findings = Vec::new();
+  walk(root, rules, true, &mut findings);
+  findings.sort_by(|a, b| a.path.cmp(&b.path));
+  findings
+}
+
+/// Picks up the result of a finished background scan.
+pub fn poll(state: &mut Themis) {
+  let findings = match &state.cleanup.scan {
+    Some(receiver) => match receiver.try_recv() {
+      Ok(findings) => findings,
+      Err(_) => return,
+    },
+    None => return,
+  };
+  state.cleanup.scan = None;
+  state.cleanup.findings = findings;
+}
+
+/// Moves everything found in `category` to the trash.
+pub fn trash_category(state: &mut Themis, category: Category) {
+  let (trashed, kept): (Vec<Finding>, Vec<Finding>) = state
+    .cleanup
+    .findings
+    .drain(..)
+    .partition(|finding| finding.category == category);
+  state.cleanup.findings = kept;
+  for finding in trashed {
+    if let Err(err) = trash::delete(&finding.path) {
+      state.errors.report(Error::Trash {
+        path: finding.path.clone(),
+        message: err.to_string(),
+      });
+      state.cleanup.findings.push(finding);
+    }
+  }
+  state.cleanup.findings.sort_by(|a, b| a.path.cmp(&b.path));
+  if let Err(err) = update_current_dir(state) {
+    state.errors.report(err);
+  }
+}
+
+/// Writes the findings as plain text next to the saved index and returns
+/// where it went.
+pub fn export(state: &Themis) -> Result<PathBuf, Error> {
+  let root = state.cleanup.root.clone().unwrap_or_default();
+  let mut report = format!("Cleanup report for {}\n", root.display());
+  for category in Category::ALL {
+    let findings: Vec<&Finding> = state.cleanup.of(category).collect();
+    let _ = write!(report, "\n# {} ({})\n", category.label(), findings.len());
+    for finding in findings {
+      let _ = writeln!(report, "{}", finding.path.display());
+    }
+  }
+  let path = state.settings.save_load.location.join("cleanup-report.txt");
+  fs::write(&path, report).map_err(|err| Error::io("write", &path, err))?;
+  Ok(path)
+}
+
+/// Collects findings below `dir` and returns whether it holds nothing but
+/// (recursively) empty folders. Those are only reported at the topmost
+/// level, unless that is the scan root itself.
+fn walk(dir: &Path, rules: &Rules, is_root: bool, findings: &mut Vec<Finding>) -> bool {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return false,
+  };
+  let mut empty_dirs = Vec::new();
+  let mut is_empty = true;
+  for entry in entries.flatten() {
+    let path = entry.path();
+    // * `DirEntry::metadata` doesn't follow symlinks, so no loops here
+    let metadata = match entry.metadata() {
+      Ok(metadata) => metadata,
+      Err(_) => {
+        is_empty = false;
+        continue;
+      }
+    };
+    if metadata.file_type().is_symlink() {
+      is_empty = false;
+      if fs::metadata(&path).is_err() {
+        findings.push(Finding {
+          path,
+          category: Category::BrokenSymlink,
+          size: 0,
+        });
+      }
+    } else if metadata.is_dir() {
+      if walk(&path, rules, false, findings) {
+        empty_dirs.push(path);
+      } else {
+        is_empty = false;
+      }
+    } else {
+      is_empty = false;
+      let category = if metadata.len() == 0 {
+        Category::ZeroByte
+      } else if rules.is_stale(&entry.file_name().to_string_lossy(), &metadata) {
+        Category::StaleTemp
+      } else {
+        continue;
+      };
+      findings.push(Finding {
+        path,
+        category,
+        size: metadata.len(),
+      });
+    }
+  }
+  if !is_empty || is_root {
+    findings.extend(empty_dirs.into_iter().map(|path| Finding {
+      path,
+      category: Category::EmptyDir,
+      size: 0,
+    }));
+  }
+  is_empty
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+  use std::fs::File;
+
+  const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+  fn rules() -> Rules {
+    Rules::new(&CleanupSettings::default(), SystemTime::now())
+  }
+
+  fn write_aged(path: &Path, days: u32) {
+    fs::write(path, "x").unwrap();
+    let file = File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - DAY * days).unwrap();
+  }
+
+  fn found(root: &Path, rules: &Rules) -> Vec<(PathBuf, Category)> {
+    find(root, rules)
+      .into_iter()
+      .map(|finding| {
+        let relative = finding.path.strip_prefix(root).unwrap().to_path_buf();
+        (relative, finding.category)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn temp_globs_need_a_match_and_an_old_file() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    write_aged(&dir.join("old.tmp"), 30);
+    write_aged(&dir.join("new.tmp"), 1);
+    write_aged(&dir.join("old.txt"), 30);
+    write_aged(&dir.join(".#draft"), 8);
+    let rules = rules();
+    let stale = |name: &str| rules.is_stale(name, &fs::metadata(dir.join(name)).unwrap());
+    assert!(stale("old.tmp"));
+    assert!(!stale("new.tmp"));
+    assert!(!stale("old.txt"));
+    assert!(stale(".#draft"));
+
+    let never = Rules::new(
+      &CleanupSettings {
+        temp_globs: vec!["[".to_owned()],
+        stale_days: 0,
+      },
+      SystemTime::now(),
+    );
+    assert!(never.patterns.is_empty());
+  }
+
+  #[test]
+  fn scan_sorts_a_tree_into_categories() {
+    let scratch = scratch();
+    let root = scratch.path();
+    fs::create_dir_all(root.join("empty/nested/deeper")).unwrap();
+    fs::create_dir_all(root.join("full/hollow")).unwrap();
+    fs::write(root.join("full/kept.txt"), "data").unwrap();
+    File::create(root.join("full/blank.txt")).unwrap();
+    std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+    std::os::unix::fs::symlink(root.join("full"), root.join("working")).unwrap();
+    write_aged(&root.join("full/download.part"), 30);
+
+    assert_eq!(
+      found(root, &rules()),
+      vec![
+        (PathBuf::from("dangling"), Category::BrokenSymlink),
+        (PathBuf::from("empty"), Category::EmptyDir),
+        (PathBuf::from("full/blank.txt"), Category::ZeroByte),
+        (PathBuf::from("full/download.part"), Category::StaleTemp),
+        (PathBuf::from("full/hollow"), Category::EmptyDir),
+      ]
+    );
+  }
+
+  #[test]
+  fn an_empty_root_is_not_reported_itself() {
+    let scratch = scratch();
+    let root = scratch.path();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    assert_eq!(found(root, &rules()), vec![(PathBuf::from("a"), Category::EmptyDir)]);
+    assert!(found(&root.join("a/b"), &rules()).is_empty());
+  }
+}
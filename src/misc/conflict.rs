@@ -0,0 +1,347 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::app::{Error, Themis};
+use crate::misc::name::{escape, path_bytes, unescape};
+use crate::misc::search::update_current_dir;
+
+/// An operation that writes to a destination path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+  Rename,
+}
+
+/// How a single name collision should be settled.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolution {
+  Overwrite,
+  Skip,
+  KeepBoth,
+  Rename(String),
+}
+
+#[derive(Clone, Default)]
+pub struct FileStat {
+  pub size: u64,
+  pub modified: Option<SystemTime>,
+  pub is_dir: bool,
+  pub hash: Option<u64>,
+}
+
+impl FileStat {
+  pub fn new(path: &Path) -> Self {
+    match fs::symlink_metadata(path) {
+      Ok(metadata) => Self {
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+        is_dir: metadata.is_dir(),
+        hash: None,
+      },
+      Err(_) => Self::default(),
+    }
+  }
+}
+
+pub struct Conflict {
+  pub operation: Operation,
+  pub source: PathBuf,
+  pub target: PathBuf,
+  pub source_stat: FileStat,
+  pub target_stat: FileStat,
+  pub rename_value: String,
+}
+
+impl Conflict {
+  pub fn new(operation: Operation, source: PathBuf, target: PathBuf) -> Self {
+    let rename_value = escape(unique_path(&target).file_name().unwrap_or_default());
+    Self {
+      operation,
+      source_stat: FileStat::new(&source),
+      target_stat: FileStat::new(&target),
+      source,
+      target,
+      rename_value,
+    }
+  }
+
+  /// Hashes both sides so the dialog can tell identical files apart from
+  /// files that only share a size and timestamp.
+  pub fn compare_contents(&mut self) {
+    if !self.source_stat.is_dir && !self.target_stat.is_dir {
+      self.source_stat.hash = hash_file(&self.source);
+      self.target_stat.hash = hash_file(&self.target);
+    }
+  }
+}
+
+/// Queue of collisions waiting on the user, plus the choice to reuse for the
+/// rest of the job once "apply to all" has been ticked.
+#[derive(Default)]
+pub struct ConflictResolver {
+  pub queue: VecDeque<Conflict>,
+  pub apply_to_all: bool,
+  pub remembered: Option<Resolution>,
+}
+
+/// Runs `operation` from `source` to `target`, going through the resolver
+/// if something already lives at `target`.
+pub fn transfer(state: &mut Themis, operation: Operation, source: PathBuf, target: PathBuf) {
+  if source == target {
+    return;
+  }
+  if fs::symlink_metadata(&target).is_err() {
+    if let Err(err) = perform(operation, &source, &target) {
+      state.errors.report(err);
+    }
+    if let Err(err) = update_current_dir(state) {
+      state.errors.report(err);
+    }
+    return;
+  }
+  let conflict = Conflict::new(operation, source, target);
+  match state.conflicts.remembered.clone() {
+    Some(resolution) => resolve(state, conflict, resolution),
+    None => state.conflicts.queue.push_back(conflict),
+  }
+}
+
+/// Settles the first queued conflict with `resolution`, remembering it for
+/// the remaining ones when "apply to all" is ticked.
+pub fn resolve_next(state: &mut Themis, resolution: Resolution) {
+  if let Some(conflict) = state.conflicts.queue.pop_front() {
+    if state.conflicts.apply_to_all {
+      // * A typed name only makes sense for the file it was typed for
+      let remembered = match resolution {
+        Resolution::Rename(_) => Resolution::KeepBoth,
+        ref other => other.clone(),
+      };
+      state.conflicts.remembered = Some(remembered.clone());
+      let queued: Vec<Conflict> = state.conflicts.queue.drain(..).collect();
+      resolve(state, conflict, resolution);
+      for conflict in queued {
+        resolve(state, conflict, remembered.clone());
+      }
+    } else {
+      resolve(state, conflict, resolution);
+    }
+  }
+  if state.conflicts.queue.is_empty() {
+    state.conflicts.apply_to_all = false;
+  }
+}
+
+/// Forgets any "apply to all" choice, so the next job asks again.
+pub fn reset(state: &mut Themis) {
+  state.conflicts.remembered = None;
+  state.conflicts.apply_to_all = false;
+}
+
+fn resolve(state: &mut Themis, conflict: Conflict, resolution: Resolution) {
+  let result = match resolution {
+    Resolution::Skip => Ok(()),
+    Resolution::Overwrite => overwrite(conflict.operation, &conflict.source, &conflict.target),
+    Resolution::KeepBoth => perform(
+      conflict.operation,
+      &conflict.source,
+      &unique_path(&conflict.target),
+    ),
+    Resolution::Rename(name) => {
+      let name = unescape(&name);
+      if let Err(err) = valid_name(&name) {
+        // * Keep the conflict around so a better name can be typed
+        state.errors.report(err);
+        state.conflicts.queue.push_front(conflict);
+        return;
+      }
+      let target = conflict.target.with_file_name(name);
+      if fs::symlink_metadata(&target).is_ok() {
+        // * The new name collides too, ask again
+        state
+          .conflicts
+          .queue
+          .push_front(Conflict::new(conflict.operation, conflict.source, target));
+        return;
+      }
+      perform(conflict.operation, &conflict.source, &target)
+    }
+  };
+  if let Err(err) = result {
+    state.errors.report(err);
+  }
+  if let Err(err) = update_current_dir(state) {
+    state.errors.report(err);
+  }
+}
+
+fn perform(operation: Operation, source: &Path, target: &Path) -> Result<(), Error> {
+  match operation {
+    Operation::Rename => fs::rename(source, target).map_err(|err| Error::io("rename", source, err)),
+  }
+}
+
+/// Replaces `target` without losing it when the operation fails: it is
+/// moved aside first and only deleted once the new one is in place.
+fn overwrite(operation: Operation, source: &Path, target: &Path) -> Result<(), Error> {
+  // * Renaming a file over a file is atomic on unix, nothing to protect
+  let plain_files = !FileStat::new(source).is_dir && !FileStat::new(target).is_dir;
+  if cfg!(unix) && operation == Operation::Rename && plain_files {
+    return perform(operation, source, target);
+  }
+  let mut aside = OsString::from(".");
+  aside.push(target.file_name().unwrap_or_default());
+  aside.push(".overwritten");
+  let aside = unique_path(&target.with_file_name(aside));
+  fs::rename(target, &aside).map_err(|err| Error::io("move aside", target, err))?;
+  match perform(operation, source, target) {
+    Ok(()) => remove(&aside),
+    Err(err) => {
+      let _ = fs::rename(&aside, target);
+      Err(err)
+    }
+  }
+}
+
+/// Rejects names that would point somewhere else than a sibling. Takes
+/// the unescaped name, so `\x2F` can't sneak a separator past it.
+pub fn valid_name(name: &OsStr) -> Result<(), Error> {
+  let bytes = path_bytes(Path::new(name));
+  let separator = |byte: &u8| *byte == b'/' || (cfg!(windows) && *byte == b'\\');
+  let invalid = bytes.is_empty()
+    || bytes == b"."
+    || bytes == b".."
+    || bytes.iter().any(|byte| separator(byte) || *byte == 0);
+  if invalid {
+    return Err(Error::Invalid(format!("\"{}\" is not a valid file name", escape(name))));
+  }
+  Ok(())
+}
+
+fn remove(path: &Path) -> Result<(), Error> {
+  let result = match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+    Ok(_) => fs::remove_file(path),
+    Err(err) => Err(err),
+  };
+  result.map_err(|err| Error::io("overwrite", path, err))
+}
+
+/// Returns `path` with a ` (n)` suffix on the stem, picking the first `n`
+/// that is not taken yet.
+pub fn unique_path(path: &Path) -> PathBuf {
+  let stem = path.file_stem().unwrap_or_default();
+  let mut index = 1;
+  loop {
+    let mut name = stem.to_os_string();
+    name.push(format!(" ({})", index));
+    if let Some(extension) = path.extension() {
+      name.push(".");
+      name.push(extension);
+    }
+    let candidate = path.with_file_name(name);
+    if fs::symlink_metadata(&candidate).is_err() {
+      return candidate;
+    }
+    index += 1;
+  }
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+  let mut file = File::open(path).ok()?;
+  let mut hasher = DefaultHasher::new();
+  let mut buffer = [0u8; 64 * 1024];
+  loop {
+    let read = file.read(&mut buffer).ok()?;
+    if read == 0 {
+      break;
+    }
+    hasher.write(&buffer[..read]);
+  }
+  Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  #[test]
+  fn unique_path_suffixes_the_stem() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    assert_eq!(unique_path(&dir.join("notes.txt")), dir.join("notes (1).txt"));
+    assert_eq!(unique_path(&dir.join("Makefile")), dir.join("Makefile (1)"));
+    assert_eq!(unique_path(&dir.join("archive.tar.gz")), dir.join("archive.tar (1).gz"));
+    // * Dotfiles are all stem, the suffix goes at the end
+    assert_eq!(unique_path(&dir.join(".bashrc")), dir.join(".bashrc (1)"));
+  }
+
+  #[test]
+  fn unique_path_skips_taken_names() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    File::create(dir.join("a (1).txt")).unwrap();
+    File::create(dir.join("a (2).txt")).unwrap();
+    assert_eq!(unique_path(&dir.join("a.txt")), dir.join("a (3).txt"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn unique_path_keeps_raw_bytes() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    let scratch = scratch();
+    let dir = scratch.path();
+    let name = std::ffi::OsStr::from_bytes(b"caf\xE9.txt");
+    let expected = OsString::from_vec(b"caf\xE9 (1).txt".to_vec());
+    assert_eq!(unique_path(&dir.join(name)), dir.join(expected));
+  }
+
+  #[test]
+  fn valid_name_rejects_paths() {
+    let valid = |name: &str| valid_name(OsStr::new(name)).is_ok();
+    assert!(valid("report.pdf"));
+    assert!(valid("..hidden"));
+    assert!(!valid(""));
+    assert!(!valid("."));
+    assert!(!valid(".."));
+    assert!(!valid("a/b"));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn valid_name_checks_the_unescaped_name() {
+    assert!(valid_name(&unescape("caf\\xE9")).is_ok());
+    assert!(valid_name(&unescape("..\\x2F..\\x2Fetc")).is_err());
+    assert!(valid_name(&unescape("a\\x00b")).is_err());
+    assert!(valid_name(&unescape("\\x2E\\x2E")).is_err());
+  }
+
+  #[test]
+  fn overwrite_replaces_a_directory() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    fs::create_dir(dir.join("old")).unwrap();
+    File::create(dir.join("old").join("inside")).unwrap();
+    fs::create_dir(dir.join("new")).unwrap();
+    overwrite(Operation::Rename, &dir.join("new"), &dir.join("old")).unwrap();
+    assert!(dir.join("old").is_dir());
+    assert!(!dir.join("old").join("inside").exists());
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+  }
+
+  #[test]
+  fn failed_overwrite_keeps_the_target() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    fs::create_dir(dir.join("target")).unwrap();
+    File::create(dir.join("target").join("kept")).unwrap();
+    assert!(overwrite(Operation::Rename, &dir.join("missing"), &dir.join("target")).is_err());
+    assert!(dir.join("target").join("kept").exists());
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+  }
+}
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The index only records how much data a file holds, so the space taken on
+/// disk is estimated by rounding up to whole clusters.
+const CLUSTER_SIZE: u64 = 4096;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct DirStats {
+  /// Apparent size of everything below the directory.
+  pub size: u64,
+  /// Estimated space taken on disk.
+  pub allocated: u64,
+  pub files: u64,
+  pub dirs: u64,
+}
+
+/// Recursive totals for every directory in the index, kept up to date from
+/// watcher events after the initial build.
+#[derive(Default)]
+pub struct DirSizes {
+  pub dirs: HashMap<PathBuf, DirStats>,
+  /// Files whose size changed since the index was built, `None` once removed.
+  touched: HashMap<PathBuf, Option<u64>>,
+  /// Directories moved since the index was built, oldest first, so files
+  /// inside can still be found in it under their old path.
+  moved: Vec<(PathBuf, PathBuf)>,
+}
+
+impl DirSizes {
+  pub fn build(filesystem: &mft_ntfs::Filesystem) -> Self {
+    Self::from_sizes(filesystem.files.iter().map(|(key, file)| (key.as_str(), file.real_size)))
+  }
+
+  /// Totals for index entries given as paths with their sizes.
+  fn from_sizes<'a>(files: impl Iterator<Item = (&'a str, u64)> + Clone) -> Self {
+    // * Anything that is a parent of another entry is a directory, the index
+    // * has no flag for the empty ones so those are asked from the disk
+    let mut dir_paths = HashSet::new();
+    for (key, _) in files.clone() {
+      for ancestor in Path::new(key).ancestors().skip(1) {
+        if !dir_paths.insert(ancestor.to_path_buf()) {
+          break;
+        }
+      }
+    }
+
+    let mut sizes = Self::default();
+    for (key, size) in files {
+      let path = Path::new(key);
+      let is_dir = dir_paths.contains(path) || (size == 0 && path.is_dir());
+      let delta = if is_dir {
+        sizes.dirs.entry(path.to_path_buf()).or_default();
+        DirStats {
+          dirs: 1,
+          ..DirStats::default()
+        }
+      } else {
+        file_stats(size)
+      };
+      sizes.add(path, delta);
+    }
+    sizes
+  }
+
+  /// Records that the file at `path` now has `size` bytes, or is gone when
+  /// `size` is `None`.
+  pub fn update_file(&mut self, filesystem: &mft_ntfs::Filesystem, path: &Path, size: Option<u64>) {
+    let indexed = |key: &str| filesystem.files.get(key).map(|file| file.real_size);
+    self.update_indexed(indexed, path, size);
+  }
+
+  /// `update_file` with the index lookup passed in.
+  fn update_indexed(
+    &mut self,
+    indexed: impl Fn(&str) -> Option<u64>,
+    path: &Path,
+    size: Option<u64>,
+  ) {
+    let old = match self.touched.get(path) {
+      Some(old) => *old,
+      None => self.original(path).to_str().and_then(indexed),
+    };
+    self.touched.insert(path.to_path_buf(), size);
+    let delta = difference(
+      size.map(file_stats).unwrap_or_default(),
+      old.map(file_stats).unwrap_or_default(),
+    );
+    self.add(path, delta);
+  }
+
+  pub fn add_dir(&mut self, path: &Path) {
+    if self.dirs.contains_key(path) {
+      return;
+    }
+    self.dirs.insert(path.to_path_buf(), DirStats::default());
+    self.add(
+      path,
+      DirStats {
+        dirs: 1,
+        ..DirStats::default()
+      },
+    );
+  }
+
+  pub fn remove_dir(&mut self, path: &Path) {
+    let mut stats = match self.dirs.remove(path) {
+      Some(stats) => stats,
+      None => return,
+    };
+    self.dirs.retain(|dir, _| !dir.starts_with(path));
+    self.touched.retain(|file, _| !file.starts_with(path));
+    stats.dirs += 1;
+    self.add(path, difference(DirStats::default(), stats));
+  }
+
+  /// Carries the totals of a moved directory over to its new place. Returns
+  /// false when `from` isn't a known directory.
+  pub fn move_dir(&mut self, from: &Path, to: &Path) -> bool {
+    let mut stats = match self.dirs.get(from) {
+      Some(stats) => *stats,
+      None => return false,
+    };
+    stats.dirs += 1;
+    self.add(from, difference(DirStats::default(), stats));
+    self.dirs = rekey(std::mem::take(&mut self.dirs), from, to);
+    self.touched = rekey(std::mem::take(&mut self.touched), from, to);
+    self.moved.push((from.to_path_buf(), to.to_path_buf()));
+    self.add(to, stats);
+    true
+  }
+
+  /// Where `path` was when the index was built.
+  fn original(&self, path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    for (from, to) in self.moved.iter().rev() {
+      if let Ok(rest) = path.strip_prefix(to) {
+        path = from.join(rest);
+      }
+    }
+    path
+  }
+
+  /// Adds `delta` to every directory above `path`. Removals come in as
+  /// wrapped negative numbers, see `difference`.
+  fn add(&mut self, path: &Path, delta: DirStats) {
+    for ancestor in path.ancestors().skip(1) {
+      let stats = self.dirs.entry(ancestor.to_path_buf()).or_default();
+      stats.size = stats.size.wrapping_add(delta.size);
+      stats.allocated = stats.allocated.wrapping_add(delta.allocated);
+      stats.files = stats.files.wrapping_add(delta.files);
+      stats.dirs = stats.dirs.wrapping_add(delta.dirs);
+    }
+  }
+}
+
+fn rekey<T>(map: HashMap<PathBuf, T>, from: &Path, to: &Path) -> HashMap<PathBuf, T> {
+  map
+    .into_iter()
+    .map(|(path, value)| match path.strip_prefix(from) {
+      Ok(rest) => (to.join(rest), value),
+      Err(_) => (path, value),
+    })
+    .collect()
+}
+
+fn file_stats(size: u64) -> DirStats {
+  DirStats {
+    size,
+    allocated: match size % CLUSTER_SIZE {
+      0 => size,
+      rest => size - rest + CLUSTER_SIZE,
+    },
+    files: 1,
+    dirs: 0,
+  }
+}
+
+fn difference(new: DirStats, old: DirStats) -> DirStats {
+  DirStats {
+    size: new.size.wrapping_sub(old.size),
+    allocated: new.allocated.wrapping_sub(old.allocated),
+    files: new.files.wrapping_sub(old.files),
+    dirs: new.dirs.wrapping_sub(old.dirs),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FILES: [(&str, u64); 6] = [
+    ("/a", 0),
+    ("/a/old", 0),
+    ("/a/old/one", 10),
+    ("/a/old/sub", 0),
+    ("/a/old/sub/two", 20),
+    ("/b", 0),
+  ];
+
+  fn indexed(key: &str) -> Option<u64> {
+    FILES.iter().find(|(path, _)| *path == key).map(|(_, size)| *size)
+  }
+
+  fn stats(sizes: &DirSizes, path: &str) -> DirStats {
+    sizes.dirs.get(Path::new(path)).copied().unwrap_or_default()
+  }
+
+  #[test]
+  fn moved_directories_keep_their_totals() {
+    let mut sizes = DirSizes::from_sizes(FILES.iter().copied());
+    assert_eq!(stats(&sizes, "/a").size, 30);
+
+    assert!(sizes.move_dir(Path::new("/a/old"), Path::new("/b/new")));
+    assert_eq!(stats(&sizes, "/a"), DirStats::default());
+    assert_eq!(stats(&sizes, "/b/new").size, 30);
+    assert_eq!(stats(&sizes, "/b/new/sub").size, 20);
+    assert_eq!(stats(&sizes, "/b").dirs, 2);
+    assert!(!sizes.dirs.contains_key(Path::new("/a/old")));
+
+    // * A file inside is still found in the index under its old path
+    sizes.update_indexed(indexed, Path::new("/b/new/one"), Some(15));
+    assert_eq!(stats(&sizes, "/b").size, 35);
+    assert_eq!(stats(&sizes, "/b").files, 2);
+  }
+}
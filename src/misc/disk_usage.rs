@@ -0,0 +1,264 @@
+use crossbeam_channel::Receiver;
+use eframe::egui::{pos2, Rect};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::app::Themis;
+use crate::misc::dir_sizes::DirSizes;
+
+#[derive(Clone)]
+pub struct UsageItem {
+  pub path: PathBuf,
+  pub name: OsString,
+  pub size: u64,
+  pub is_dir: bool,
+}
+
+/// The disk usage view of one directory: its direct children with their
+/// recursive sizes, biggest first.
+#[derive(Default)]
+pub struct DiskUsage {
+  pub root: Option<PathBuf>,
+  pub items: Vec<UsageItem>,
+  pub total: u64,
+  /// Set while a background scan is running for `root`.
+  pub scan: Option<Receiver<Vec<UsageItem>>>,
+}
+
+/// Starts computing usage for `root`, from the index when it has been loaded
+/// or with a background scan otherwise.
+pub fn open(state: &mut Themis, root: PathBuf) {
+  state.disk_usage.root = Some(root.clone());
+  state.disk_usage.scan = None;
+  if state.filesystem.files.is_empty() {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    state.disk_usage.items = Vec::new();
+    state.disk_usage.total = 0;
+    state.disk_usage.scan = Some(receiver);
+    thread::spawn(move || {
+      let _ = sender.send(scan(&root));
+    });
+  } else {
+    let items = from_index(&state.filesystem, &state.dir_sizes, &root);
+    set_items(state, items);
+  }
+}
+
+/// Picks up the result of a finished background scan.
+pub fn poll(state: &mut Themis) {
+  let items = match &state.disk_usage.scan {
+    Some(receiver) => match receiver.try_recv() {
+      Ok(items) => items,
+      Err(_) => return,
+    },
+    None => return,
+  };
+  state.disk_usage.scan = None;
+  set_items(state, items);
+}
+
+fn set_items(state: &mut Themis, mut items: Vec<UsageItem>) {
+  items.sort_by_key(|item| std::cmp::Reverse(item.size));
+  state.disk_usage.total = items.iter().map(|item| item.size).sum();
+  state.disk_usage.items = items;
+}
+
+/// Sizes of the children of `root`, with directory totals from the index.
+pub fn from_index(
+  filesystem: &mft_ntfs::Filesystem,
+  dir_sizes: &DirSizes,
+  root: &Path,
+) -> Vec<UsageItem> {
+  let dir = match fs::read_dir(root) {
+    Ok(dir) => dir,
+    Err(_) => return Vec::new(),
+  };
+  dir
+    .flatten()
+    .map(|entry| {
+      let path = entry.path();
+      let is_dir = matches!(entry.file_type(), Ok(file_type) if file_type.is_dir());
+      let size = if is_dir {
+        dir_sizes.dirs.get(&path).map_or(0, |stats| stats.size)
+      } else {
+        match path.to_str().and_then(|key| filesystem.files.get(key)) {
+          Some(file) => file.real_size,
+          None => entry.metadata().map_or(0, |metadata| metadata.len()),
+        }
+      };
+      UsageItem {
+        name: entry.file_name(),
+        path,
+        size,
+        is_dir,
+      }
+    })
+    .collect()
+}
+
+/// Sizes of the children of `root`, walking the disk.
+pub fn scan(root: &Path) -> Vec<UsageItem> {
+  let dir = match fs::read_dir(root) {
+    Ok(dir) => dir,
+    Err(_) => return Vec::new(),
+  };
+  dir
+    .flatten()
+    .filter_map(|entry| {
+      let metadata = entry.metadata().ok()?;
+      let path = entry.path();
+      let size = if metadata.is_dir() {
+        dir_size(&path)
+      } else {
+        metadata.len()
+      };
+      Some(UsageItem {
+        name: entry.file_name(),
+        path,
+        size,
+        is_dir: metadata.is_dir(),
+      })
+    })
+    .collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+  let dir = match fs::read_dir(path) {
+    Ok(dir) => dir,
+    Err(_) => return 0,
+  };
+  dir
+    .flatten()
+    .map(|entry| match entry.metadata() {
+      // * `DirEntry::metadata` doesn't follow symlinks, so no loops here
+      Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+      Ok(metadata) => metadata.len(),
+      Err(_) => 0,
+    })
+    .sum()
+}
+
+/// Squarified treemap layout (Bruls, Huizing, van Wijk). `sizes` must be
+/// sorted biggest first, the returned rects are in the same order.
+pub fn squarify(sizes: &[u64], rect: Rect) -> Vec<Rect> {
+  let total: u64 = sizes.iter().sum();
+  if total == 0 || rect.area() <= 0.0 {
+    return Vec::new();
+  }
+  let scale = rect.area() / total as f32;
+  let areas: Vec<f32> = sizes.iter().map(|&size| size as f32 * scale).collect();
+
+  let mut rects = Vec::with_capacity(areas.len());
+  let mut remaining = rect;
+  let mut row: Vec<f32> = Vec::new();
+  let mut index = 0;
+  while index < areas.len() {
+    let side = remaining.width().min(remaining.height());
+    let mut candidate = row.clone();
+    candidate.push(areas[index]);
+    if row.is_empty() || worst(&candidate, side) <= worst(&row, side) {
+      row = candidate;
+      index += 1;
+    } else {
+      remaining = layout_row(&row, remaining, &mut rects);
+      row.clear();
+    }
+  }
+  if !row.is_empty() {
+    layout_row(&row, remaining, &mut rects);
+  }
+  rects
+}
+
+/// Worst aspect ratio in `row` when laid out along a side of length `side`.
+fn worst(row: &[f32], side: f32) -> f32 {
+  let sum: f32 = row.iter().sum();
+  let max = row.iter().cloned().fold(f32::MIN, f32::max);
+  let min = row.iter().cloned().fold(f32::MAX, f32::min);
+  let side = side * side;
+  let sum = sum * sum;
+  (side * max / sum).max(sum / (side * min))
+}
+
+/// Places `row` along the shorter side of `rect` and returns what is left.
+fn layout_row(row: &[f32], rect: Rect, rects: &mut Vec<Rect>) -> Rect {
+  let sum: f32 = row.iter().sum();
+  if rect.width() >= rect.height() {
+    let width = sum / rect.height();
+    let mut y = rect.min.y;
+    for area in row {
+      let height = area / width;
+      rects.push(Rect::from_min_max(
+        pos2(rect.min.x, y),
+        pos2(rect.min.x + width, y + height),
+      ));
+      y += height;
+    }
+    Rect::from_min_max(pos2(rect.min.x + width, rect.min.y), rect.max)
+  } else {
+    let height = sum / rect.width();
+    let mut x = rect.min.x;
+    for area in row {
+      let width = area / height;
+      rects.push(Rect::from_min_max(
+        pos2(x, rect.min.y),
+        pos2(x + width, rect.min.y + height),
+      ));
+      x += width;
+    }
+    Rect::from_min_max(pos2(rect.min.x, rect.min.y + height), rect.max)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bounds() -> Rect {
+    Rect::from_min_max(pos2(0.0, 0.0), pos2(600.0, 400.0))
+  }
+
+  #[test]
+  fn squarify_fills_the_rect_in_proportion() {
+    let sizes = [6, 6, 4, 3, 2, 2, 1];
+    let rects = squarify(&sizes, bounds());
+    assert_eq!(rects.len(), sizes.len());
+    let total: u64 = sizes.iter().sum();
+    for (size, rect) in sizes.iter().zip(&rects) {
+      let expected = bounds().area() * *size as f32 / total as f32;
+      assert!((rect.area() - expected).abs() < 0.5, "{} vs {}", rect.area(), expected);
+      assert!(bounds().expand(0.01).contains_rect(*rect));
+    }
+    let covered: f32 = rects.iter().map(|rect| rect.area()).sum();
+    assert!((covered - bounds().area()).abs() < 1.0);
+  }
+
+  #[test]
+  fn squarify_does_not_overlap() {
+    let rects = squarify(&[5, 4, 3, 2, 1], bounds());
+    for (index, a) in rects.iter().enumerate() {
+      for b in &rects[index + 1..] {
+        let width = a.max.x.min(b.max.x) - a.min.x.max(b.min.x);
+        let height = a.max.y.min(b.max.y) - a.min.y.max(b.min.y);
+        assert!(width < 0.01 || height < 0.01, "{:?} overlaps {:?}", a, b);
+      }
+    }
+  }
+
+  #[test]
+  fn squarify_keeps_squares_square() {
+    let rects = squarify(&[1, 1, 1, 1], Rect::from_min_max(pos2(0.0, 0.0), pos2(100.0, 100.0)));
+    for rect in rects {
+      assert!((rect.width() - 50.0).abs() < 0.01 && (rect.height() - 50.0).abs() < 0.01);
+    }
+  }
+
+  #[test]
+  fn squarify_skips_empty_input() {
+    assert!(squarify(&[], bounds()).is_empty());
+    assert!(squarify(&[0, 0], bounds()).is_empty());
+    assert!(squarify(&[1], Rect::from_min_max(pos2(0.0, 0.0), pos2(0.0, 10.0))).is_empty());
+  }
+}
@@ -0,0 +1,447 @@
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+
+use crate::app::{Error, Themis};
+use crate::misc::name::escape;
+use crate::misc::search::update_current_dir;
+
+/// Bytes hashed in the cheap second pass, before hashing whole files.
+const PREFIX_SIZE: usize = 16 * 1024;
+
+#[derive(Clone)]
+pub struct DuplicateGroup {
+  pub size: u64,
+  pub hash: String,
+  pub paths: Vec<PathBuf>,
+  /// What each of `paths` looked like when it was hashed.
+  stamps: Vec<Stamp>,
+  /// Index into `paths` of the copy to keep.
+  pub keep: usize,
+}
+
+impl DuplicateGroup {
+  pub fn wasted(&self) -> u64 {
+    self.size * (self.paths.len() as u64 - 1)
+  }
+
+  /// Fails unless the copy at `position` is still the file that was hashed.
+  fn unchanged(&self, position: usize) -> Result<(), Error> {
+    let path = &self.paths[position];
+    match fs::symlink_metadata(path) {
+      Ok(metadata) if Stamp::of(&metadata) == self.stamps[position] => Ok(()),
+      _ => Err(Error::Invalid(format!(
+        "{} changed since the search, search again",
+        escape(path.as_os_str())
+      ))),
+    }
+  }
+}
+
+/// Enough of a file's metadata to tell it was changed or replaced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stamp {
+  size: u64,
+  modified: Option<SystemTime>,
+  identity: Option<(u64, u64)>,
+}
+
+impl Stamp {
+  fn of(metadata: &Metadata) -> Self {
+    Self {
+      size: metadata.len(),
+      modified: metadata.modified().ok(),
+      identity: identity(metadata),
+    }
+  }
+}
+
+pub enum DuplicateMessage {
+  Progress(String),
+  Done(Vec<DuplicateGroup>),
+}
+
+#[derive(Default)]
+pub struct Duplicates {
+  /// Directory to search, `None` for the whole index.
+  pub scope: Option<PathBuf>,
+  pub groups: Vec<DuplicateGroup>,
+  pub status: String,
+  pub receiver: Option<Receiver<DuplicateMessage>>,
+}
+
+/// Kicks off a search in the background, see `poll` for the results.
+pub fn find(state: &mut Themis, scope: Option<PathBuf>) {
+  let candidates = if state.filesystem.files.is_empty() {
+    None
+  } else {
+    Some(
+      state
+        .filesystem
+        .files
+        .iter()
+        .map(|(key, file)| (PathBuf::from(key), file.real_size))
+        .filter(|(path, size)| {
+          *size > 0
+            && !state.dir_sizes.dirs.contains_key(path)
+            && !matches!(&scope, Some(scope) if !path.starts_with(scope))
+        })
+        .collect::<Vec<_>>(),
+    )
+  };
+  let root = scope.clone().unwrap_or_else(|| state.current_path.clone());
+  let (sender, receiver) = unbounded();
+  state.duplicates.scope = scope;
+  state.duplicates.groups = Vec::new();
+  state.duplicates.status = "Collecting files...".to_owned();
+  state.duplicates.receiver = Some(receiver);
+  thread::spawn(move || {
+    let candidates = candidates.unwrap_or_else(|| {
+      let mut files = Vec::new();
+      walk(&root, &mut files);
+      files
+    });
+    let groups = group(candidates, &sender);
+    let _ = sender.send(DuplicateMessage::Done(groups));
+  });
+}
+
+pub fn poll(state: &mut Themis) {
+  let receiver = match &state.duplicates.receiver {
+    Some(receiver) => receiver,
+    None => return,
+  };
+  for message in receiver.try_iter() {
+    match message {
+      DuplicateMessage::Progress(status) => state.duplicates.status = status,
+      DuplicateMessage::Done(groups) => {
+        let wasted: u64 = groups.iter().map(DuplicateGroup::wasted).sum();
+        state.duplicates.status = format!(
+          "{} groups, {} wasted",
+          groups.len(),
+          bytesize::ByteSize(wasted)
+        );
+        state.duplicates.groups = groups;
+        state.duplicates.receiver = None;
+        return;
+      }
+    }
+  }
+}
+
+/// Moves every copy except the kept one to the trash. Copies that changed
+/// since they were hashed are left alone, and so is everything when the
+/// kept one did.
+pub fn trash_others(state: &mut Themis, index: usize) {
+  let group = state.duplicates.groups.remove(index);
+  if let Err(err) = group.unchanged(group.keep) {
+    state.errors.report(err);
+    return;
+  }
+  for (position, path) in group.paths.iter().enumerate() {
+    if position != group.keep {
+      if let Err(err) = group.unchanged(position) {
+        state.errors.report(err);
+        continue;
+      }
+      if let Err(err) = trash::delete(path) {
+        state.errors.report(Error::Trash {
+          path: path.clone(),
+          message: err.to_string(),
+        });
+      }
+    }
+  }
+  if let Err(err) = update_current_dir(state) {
+    state.errors.report(err);
+  }
+}
+
+/// Replaces every copy except the kept one with a hardlink to it, with the
+/// same checks as `trash_others`.
+pub fn hardlink_others(state: &mut Themis, index: usize) {
+  let group = state.duplicates.groups.remove(index);
+  if let Err(err) = group.unchanged(group.keep) {
+    state.errors.report(err);
+    return;
+  }
+  let keep = &group.paths[group.keep];
+  for (position, path) in group.paths.iter().enumerate() {
+    if position != group.keep {
+      if let Err(err) = group.unchanged(position).and_then(|()| hardlink(keep, path)) {
+        state.errors.report(err);
+      }
+    }
+  }
+  if let Err(err) = update_current_dir(state) {
+    state.errors.report(err);
+  }
+}
+
+fn hardlink(keep: &Path, path: &Path) -> Result<(), Error> {
+  // * Link next to the duplicate first, so a failure never loses the file
+  let temporary = path.with_file_name(format!(
+    ".{}.themis-link",
+    path.file_name().unwrap_or_default().to_string_lossy()
+  ));
+  fs::hard_link(keep, &temporary).map_err(|err| Error::io("hardlink", path, err))?;
+  fs::rename(&temporary, path).map_err(|err| {
+    let _ = fs::remove_file(&temporary);
+    Error::io("replace", path, err)
+  })
+}
+
+fn walk(dir: &Path, files: &mut Vec<(PathBuf, u64)>) {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  for entry in entries.flatten() {
+    match entry.metadata() {
+      Ok(metadata) if metadata.is_dir() => walk(&entry.path(), files),
+      Ok(metadata) if metadata.is_file() && metadata.len() > 0 => {
+        files.push((entry.path(), metadata.len()))
+      }
+      _ => {}
+    }
+  }
+}
+
+/// Narrows `candidates` down by size, then by a hash of the first few
+/// kilobytes, then by a hash of the whole file.
+fn group(
+  candidates: Vec<(PathBuf, u64)>,
+  progress: &Sender<DuplicateMessage>,
+) -> Vec<DuplicateGroup> {
+  // * Only files sharing a size with another one are worth a look on disk
+  let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+  for (path, size) in candidates {
+    by_size.entry(size).or_default().push(path);
+  }
+  let shared = by_size.into_values().filter(|paths| paths.len() > 1).flatten();
+  let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+  let mut stamps = HashMap::new();
+  for (path, stamp) in distinct(shared.collect()) {
+    by_size.entry(stamp.size).or_default().push(path.clone());
+    stamps.insert(path, stamp);
+  }
+  let by_size: Vec<(u64, Vec<PathBuf>)> = by_size
+    .into_iter()
+    .filter(|(_, paths)| paths.len() > 1)
+    .collect();
+
+  let count: usize = by_size.iter().map(|(_, paths)| paths.len()).sum();
+  let _ = progress.send(DuplicateMessage::Progress(format!(
+    "Hashing the start of {} files...",
+    count
+  )));
+  let by_prefix = split(by_size, Some(PREFIX_SIZE));
+
+  let count: usize = by_prefix.values().map(Vec::len).sum();
+  let _ = progress.send(DuplicateMessage::Progress(format!(
+    "Hashing {} files completely...",
+    count
+  )));
+  let by_hash = split(
+    by_prefix
+      .into_iter()
+      .map(|((size, _), paths)| (size, paths))
+      .collect(),
+    None,
+  );
+
+  let mut groups: Vec<DuplicateGroup> = by_hash
+    .into_iter()
+    .map(|((size, hash), mut paths)| {
+      paths.sort();
+      DuplicateGroup {
+        size,
+        hash: hash.to_hex().to_string(),
+        stamps: paths.iter().map(|path| stamps[path]).collect(),
+        paths,
+        keep: 0,
+      }
+    })
+    .collect();
+  groups.sort_by_key(|group| std::cmp::Reverse(group.wasted()));
+  groups
+}
+
+/// Regular files as they are now, each file once. Symlinks and further
+/// hardlinks point at data we already have, so they aren't copies.
+fn distinct(mut paths: Vec<PathBuf>) -> Vec<(PathBuf, Stamp)> {
+  paths.sort();
+  let mut seen = HashSet::new();
+  paths
+    .into_iter()
+    .filter_map(|path| {
+      let metadata = fs::symlink_metadata(&path).ok()?;
+      if !metadata.is_file() || metadata.len() == 0 {
+        return None;
+      }
+      let stamp = Stamp::of(&metadata);
+      match stamp.identity {
+        Some(identity) if !seen.insert(identity) => None,
+        _ => Some((path, stamp)),
+      }
+    })
+    .collect()
+}
+
+/// Device and inode, the same for every hardlink of a file.
+#[cfg(unix)]
+fn identity(metadata: &Metadata) -> Option<(u64, u64)> {
+  use std::os::unix::fs::MetadataExt;
+  Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+  None
+}
+
+/// Hashes every path (or its first `limit` bytes) on worker threads and
+/// regroups by size and hash, dropping anything left on its own.
+fn split(
+  by_size: Vec<(u64, Vec<PathBuf>)>,
+  limit: Option<usize>,
+) -> HashMap<(u64, blake3::Hash), Vec<PathBuf>> {
+  let (jobs, job_receiver) = unbounded::<(u64, PathBuf)>();
+  let (results, result_receiver) = unbounded();
+  let workers = thread::available_parallelism().map_or(4, |count| count.get());
+  for _ in 0..workers {
+    let job_receiver = job_receiver.clone();
+    let results = results.clone();
+    thread::spawn(move || {
+      for (size, path) in job_receiver {
+        if let Some(hash) = hash_file(&path, limit) {
+          let _ = results.send((size, hash, path));
+        }
+      }
+    });
+  }
+  drop(results);
+  for (size, paths) in by_size {
+    for path in paths {
+      let _ = jobs.send((size, path));
+    }
+  }
+  drop(jobs);
+
+  let mut groups: HashMap<(u64, blake3::Hash), Vec<PathBuf>> = HashMap::new();
+  for (size, hash, path) in result_receiver {
+    groups.entry((size, hash)).or_default().push(path);
+  }
+  groups.retain(|_, paths| paths.len() > 1);
+  groups
+}
+
+fn hash_file(path: &Path, limit: Option<usize>) -> Option<blake3::Hash> {
+  let mut file = File::open(path).ok()?;
+  let mut hasher = blake3::Hasher::new();
+  let mut buffer = vec![0u8; 64 * 1024];
+  let mut remaining = limit.unwrap_or(usize::MAX);
+  while remaining > 0 {
+    let wanted = buffer.len().min(remaining);
+    let read = file.read(&mut buffer[..wanted]).ok()?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+    remaining -= read;
+  }
+  Some(hasher.finalize())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  #[test]
+  fn links_are_not_copies() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    fs::write(dir.join("a"), "same").unwrap();
+    fs::write(dir.join("d"), "same").unwrap();
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    std::os::unix::fs::symlink(dir.join("a"), dir.join("c")).unwrap();
+    let paths = ["a", "b", "c", "d"].iter().map(|name| dir.join(name)).collect();
+    let distinct: Vec<PathBuf> = distinct(paths).into_iter().map(|(path, _)| path).collect();
+    assert_eq!(distinct, vec![dir.join("a"), dir.join("d")]);
+  }
+
+  /// Three copies and one that only differs after the hashed prefix.
+  fn copies(dir: &Path) -> Vec<(PathBuf, u64)> {
+    let contents = vec![7u8; PREFIX_SIZE + 10];
+    let mut different = contents.clone();
+    *different.last_mut().unwrap() = 8;
+    for name in ["a", "b", "c"] {
+      fs::write(dir.join(name), &contents).unwrap();
+    }
+    fs::write(dir.join("d"), &different).unwrap();
+    let size = contents.len() as u64;
+    ["a", "b", "c", "d"].iter().map(|name| (dir.join(name), size)).collect()
+  }
+
+  #[test]
+  fn split_narrows_by_prefix_then_contents() {
+    let scratch = scratch();
+    let candidates = copies(scratch.path());
+    let size = candidates[0].1;
+    let paths: Vec<PathBuf> = candidates.into_iter().map(|(path, _)| path).collect();
+    let by_prefix = split(vec![(size, paths.clone())], Some(PREFIX_SIZE));
+    assert_eq!(by_prefix.values().map(Vec::len).collect::<Vec<_>>(), vec![4]);
+    let by_hash = split(vec![(size, paths.clone())], None);
+    let mut found: Vec<PathBuf> = by_hash.into_values().flatten().collect();
+    found.sort();
+    assert_eq!(found, paths[..3]);
+  }
+
+  #[test]
+  fn group_finds_the_copies() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    let candidates = copies(dir);
+    let size = candidates[0].1;
+    let (sender, _receiver) = unbounded();
+    let groups = group(candidates, &sender);
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].paths, vec![dir.join("a"), dir.join("b"), dir.join("c")]);
+    assert_eq!(groups[0].wasted(), 2 * size);
+  }
+
+  #[test]
+  fn changed_copies_are_left_alone() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    let (sender, _receiver) = unbounded();
+    let group = group(copies(dir), &sender).remove(0);
+    assert!(group.unchanged(0).is_ok());
+    fs::write(dir.join("b"), "edited").unwrap();
+    assert!(group.unchanged(1).is_err());
+    fs::remove_file(dir.join("c")).unwrap();
+    assert!(group.unchanged(2).is_err());
+  }
+
+  #[test]
+  fn hardlinks_replace_copies() {
+    use std::os::unix::fs::MetadataExt;
+    let scratch = scratch();
+    let dir = scratch.path();
+    let (sender, _receiver) = unbounded();
+    let group = group(copies(dir), &sender).remove(0);
+    hardlink(&dir.join("a"), &dir.join("b")).unwrap();
+    let (a, b) = (fs::metadata(dir.join("a")).unwrap(), fs::metadata(dir.join("b")).unwrap());
+    assert_eq!((a.dev(), a.ino()), (b.dev(), b.ino()));
+    assert_eq!(a.nlink(), 2);
+    assert_eq!(fs::read(dir.join("b")).unwrap(), fs::read(dir.join("c")).unwrap());
+    // * Nothing is left next to the copy, and it now counts as changed
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 4);
+    assert!(group.unchanged(1).is_err());
+  }
+}
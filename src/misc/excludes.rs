@@ -0,0 +1,196 @@
+//! Leaves out what the user doesn't want to see in search results and the
+//! index: hidden files, their own exclude globs, and everything `.gitignore`,
+//! `.ignore` and the global git excludes file ignore.
+
+use glob::Pattern;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ui::settings::SearchSettings;
+
+struct IgnoreFiles {
+  /// `.gitignore` and `.ignore` of a directory, the latter winning.
+  rules: Option<Gitignore>,
+  /// Rules further up don't apply past the top of a repository.
+  is_repo_root: bool,
+}
+
+pub struct Excludes {
+  /// Hidden names and globs only count below this, so searching inside a
+  /// hidden directory still works.
+  base: PathBuf,
+  show_hidden: bool,
+  globs: Vec<Pattern>,
+  respect_ignore: bool,
+  global: Option<Gitignore>,
+  dirs: HashMap<PathBuf, IgnoreFiles>,
+}
+
+impl Excludes {
+  pub fn new(base: &Path, settings: &SearchSettings) -> Self {
+    let global = if settings.respect_ignore {
+      // * Rooted at `/` so absolute paths can be matched against it
+      let (global, _) = GitignoreBuilder::new("/").build_global();
+      Some(global).filter(|global| !global.is_empty())
+    } else {
+      None
+    };
+    Self {
+      base: base.to_path_buf(),
+      show_hidden: settings.show_hidden,
+      globs: settings
+        .exclude_globs
+        .iter()
+        .filter(|glob| !glob.is_empty())
+        .filter_map(|glob| Pattern::new(glob).ok())
+        .collect(),
+      respect_ignore: settings.respect_ignore,
+      global,
+      dirs: HashMap::new(),
+    }
+  }
+
+  /// For filtering the index, where hidden files are always kept.
+  pub fn for_index(settings: &SearchSettings) -> Self {
+    Self {
+      show_hidden: true,
+      ..Self::new(Path::new("/"), settings)
+    }
+  }
+
+  /// Whether anything would be left out at all.
+  pub fn is_active(&self) -> bool {
+    !self.show_hidden || !self.globs.is_empty() || self.respect_ignore
+  }
+
+  pub fn is_excluded(&mut self, path: &Path, is_dir: bool) -> bool {
+    let relative = path.strip_prefix(&self.base).unwrap_or(path);
+    for component in relative.components() {
+      let name = component.as_os_str().to_string_lossy();
+      if !self.show_hidden && name.starts_with('.') {
+        return true;
+      }
+      if self.globs.iter().any(|glob| glob.matches(&name)) {
+        return true;
+      }
+    }
+    if !self.respect_ignore {
+      return false;
+    }
+    if path.components().any(|component| component.as_os_str() == ".git") {
+      return true;
+    }
+
+    // * The closest ignore file decides, like git does
+    for dir in path.ancestors().skip(1) {
+      let files = self.dirs.entry(dir.to_path_buf()).or_insert_with(|| read_ignore_files(dir));
+      if let Some(rules) = &files.rules {
+        let matched = rules.matched_path_or_any_parents(path, is_dir);
+        if matched.is_ignore() {
+          return true;
+        }
+        if matched.is_whitelist() {
+          return false;
+        }
+      }
+      if files.is_repo_root {
+        break;
+      }
+    }
+    match &self.global {
+      Some(global) if path.has_root() => global.matched_path_or_any_parents(path, is_dir).is_ignore(),
+      _ => false,
+    }
+  }
+}
+
+fn read_ignore_files(dir: &Path) -> IgnoreFiles {
+  let mut builder = GitignoreBuilder::new(dir);
+  let mut found = false;
+  for name in [".gitignore", ".ignore"] {
+    let file = dir.join(name);
+    if file.is_file() {
+      // * A broken line only loses that pattern, the rest still applies
+      let _ = builder.add(file);
+      found = true;
+    }
+  }
+  IgnoreFiles {
+    rules: if found { builder.build().ok() } else { None },
+    is_repo_root: dir.join(".git").exists(),
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+  use std::fs;
+
+  fn settings(show_hidden: bool, globs: &[&str], respect_ignore: bool) -> SearchSettings {
+    SearchSettings {
+      show_hidden,
+      exclude_globs: globs.iter().map(|glob| glob.to_string()).collect(),
+      respect_ignore,
+      ..SearchSettings::default()
+    }
+  }
+
+  #[test]
+  fn hidden_names_only_count_below_the_base() {
+    let base = Path::new("/home/me/.config");
+    let mut excludes = Excludes::new(base, &settings(false, &[], false));
+    assert!(excludes.is_active());
+    assert!(!excludes.is_excluded(Path::new("/home/me/.config/app/settings.json"), false));
+    assert!(excludes.is_excluded(Path::new("/home/me/.config/app/.cache"), true));
+    assert!(excludes.is_excluded(Path::new("/home/me/.config/.git/HEAD"), false));
+
+    let mut index = Excludes::for_index(&settings(false, &[], false));
+    assert!(!index.is_excluded(Path::new("/home/me/.config/app/.cache"), true));
+  }
+
+  #[test]
+  fn globs_match_any_name_below_the_base() {
+    let settings_with = |globs: &[&str]| settings(true, globs, false);
+    let globs = settings_with(&["node_modules", "*.o", ""]);
+    let mut excludes = Excludes::new(Path::new("/src"), &globs);
+    assert!(excludes.is_excluded(Path::new("/src/web/node_modules/left-pad/index.js"), false));
+    assert!(excludes.is_excluded(Path::new("/src/main.o"), false));
+    assert!(!excludes.is_excluded(Path::new("/src/main.rs"), false));
+    // * The base itself may be named anything
+    let globs = settings_with(&["node_modules"]);
+    let mut inside = Excludes::new(Path::new("/src/node_modules"), &globs);
+    assert!(!inside.is_excluded(Path::new("/src/node_modules/left-pad"), true));
+    // * Empty rows in the settings exclude nothing
+    assert!(!Excludes::new(Path::new("/"), &settings_with(&[""])).is_active());
+  }
+
+  #[test]
+  fn a_nested_whitelist_overrides_the_parent() {
+    let scratch = scratch();
+    let repo = scratch.path();
+    fs::create_dir_all(repo.join(".git")).unwrap();
+    fs::create_dir_all(repo.join("keep")).unwrap();
+    fs::write(repo.join(".gitignore"), "*.log\n").unwrap();
+    fs::write(repo.join("keep").join(".gitignore"), "!important.log\n").unwrap();
+    let mut excludes = Excludes::new(repo, &settings(true, &[], true));
+    assert!(excludes.is_excluded(&repo.join("build.log"), false));
+    assert!(excludes.is_excluded(&repo.join("keep").join("debug.log"), false));
+    assert!(!excludes.is_excluded(&repo.join("keep").join("important.log"), false));
+    assert!(!excludes.is_excluded(&repo.join("keep").join("notes.txt"), false));
+    assert!(excludes.is_excluded(&repo.join(".git").join("HEAD"), false));
+  }
+
+  #[test]
+  fn rules_stop_at_the_repository_root() {
+    let scratch = scratch();
+    let outer = scratch.path();
+    let inner = outer.join("vendor").join("lib");
+    fs::create_dir_all(inner.join(".git")).unwrap();
+    fs::write(outer.join(".gitignore"), "secret*\n").unwrap();
+    let mut excludes = Excludes::new(outer, &settings(true, &[], true));
+    assert!(excludes.is_excluded(&outer.join("vendor").join("secret.txt"), false));
+    assert!(!excludes.is_excluded(&inner.join("secret.txt"), false));
+  }
+}
@@ -0,0 +1,129 @@
+//! The `org.freedesktop.FileManager1` service other apps use for "show in
+//! folder". Commands go through the same channel as `ipc`.
+
+use crossbeam_channel::Sender;
+use eframe::egui;
+use std::path::PathBuf;
+
+use crate::app::Error;
+use crate::misc::ipc::Command;
+use crate::misc::open_with::path_from_uri;
+
+const NAME: &str = "org.freedesktop.FileManager1";
+const PATH: &str = "/org/freedesktop/FileManager1";
+
+struct FileManager {
+  sender: Sender<Command>,
+  ctx: egui::Context,
+}
+
+impl FileManager {
+  fn send(&self, uris: Vec<String>, command: fn(PathBuf) -> Command) {
+    for path in uris.iter().filter_map(|uri| path_from_uri(uri)) {
+      let _ = self.sender.send(command(path));
+    }
+    self.ctx.request_repaint();
+  }
+}
+
+#[zbus::interface(name = "org.freedesktop.FileManager1")]
+impl FileManager {
+  #[zbus(name = "ShowFolders")]
+  fn show_folders(&self, uris: Vec<String>, _startup_id: String) {
+    self.send(uris, |path| Command::Open { path });
+  }
+
+  #[zbus(name = "ShowItems")]
+  fn show_items(&self, uris: Vec<String>, _startup_id: String) {
+    self.send(uris, |path| Command::RevealAndSelect { path });
+  }
+
+  #[zbus(name = "ShowItemProperties")]
+  fn show_item_properties(&self, uris: Vec<String>, _startup_id: String) {
+    self.send(uris, |path| Command::ShowProperties { path });
+  }
+}
+
+/// Owns the service name on the session bus for as long as the returned
+/// connection is kept around.
+pub fn start(ctx: egui::Context, sender: Sender<Command>) -> Result<zbus::blocking::Connection, Error> {
+  serve(zbus::blocking::connection::Builder::session(), ctx, sender)
+}
+
+fn serve(
+  builder: zbus::Result<zbus::blocking::connection::Builder<'static>>,
+  ctx: egui::Context,
+  sender: Sender<Command>,
+) -> Result<zbus::blocking::Connection, Error> {
+  let service = FileManager { sender, ctx };
+  builder
+    .and_then(|builder| builder.name(NAME))
+    .and_then(|builder| builder.serve_at(PATH, service))
+    .and_then(|builder| builder.build())
+    .map_err(|err| Error::Bus(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+  use std::io::{BufRead, BufReader};
+  use std::process::{Child, Command as Process, Stdio};
+
+  /// A session bus of our own, so the test neither needs nor disturbs the
+  /// user's. `None` where dbus-daemon isn't installed.
+  struct Bus {
+    daemon: Child,
+    address: String,
+  }
+
+  impl Bus {
+    fn start() -> Option<Self> {
+      let mut daemon = Process::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+      let mut address = String::new();
+      BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+      Some(Self {
+        daemon,
+        address: address.trim().to_string(),
+      })
+    }
+
+    fn builder(&self) -> zbus::Result<zbus::blocking::connection::Builder<'static>> {
+      zbus::blocking::connection::Builder::address(self.address.as_str())
+    }
+  }
+
+  impl Drop for Bus {
+    fn drop(&mut self) {
+      let _ = self.daemon.kill();
+      let _ = self.daemon.wait();
+    }
+  }
+
+  #[test]
+  fn show_items_reveals_the_files() {
+    let bus = match Bus::start() {
+      Some(bus) => bus,
+      None => return eprintln!("dbus-daemon not found, skipping"),
+    };
+    let scratch = scratch();
+    let file = scratch.path().join("notes.txt");
+    std::fs::write(&file, "").unwrap();
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let _service = serve(bus.builder(), egui::Context::default(), sender).unwrap();
+
+    let client = bus.builder().and_then(|builder| builder.build()).unwrap();
+    let uri = format!("file://{}", file.display());
+    client
+      .call_method(Some(NAME), PATH, Some(NAME), "ShowItems", &(vec![uri], ""))
+      .unwrap();
+    match receiver.try_recv() {
+      Ok(Command::RevealAndSelect { path }) => assert_eq!(path, file),
+      other => panic!("expected a reveal, got {:?}", other),
+    }
+  }
+}
@@ -0,0 +1,152 @@
+//! View filters for the listing, part of each directory's view.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::app::DirEntry;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Kind {
+  All,
+  Files,
+  Dirs,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ListingFilter {
+  pub show_hidden: bool,
+  /// Names ending in `~`, left behind by editors.
+  pub show_backups: bool,
+  pub kind: Kind,
+  /// Only files with this extension, directories stay so they can still be
+  /// opened. Empty shows every file.
+  pub extension: String,
+  /// Part of the name, ignoring case. Only for the current visit, so it's
+  /// never stored with the view.
+  #[serde(skip)]
+  pub quick: String,
+}
+
+impl Default for ListingFilter {
+  fn default() -> Self {
+    Self {
+      show_hidden: true,
+      show_backups: true,
+      kind: Kind::All,
+      extension: "".to_owned(),
+      quick: "".to_owned(),
+    }
+  }
+}
+
+impl ListingFilter {
+  pub fn matches(&self, entry: &DirEntry) -> bool {
+    let name = entry.display_name();
+    if !self.show_hidden && name.starts_with('.') {
+      return false;
+    }
+    if !self.show_backups && name.ends_with('~') {
+      return false;
+    }
+    match self.kind {
+      Kind::Files if entry.is_dir => return false,
+      Kind::Dirs if !entry.is_dir => return false,
+      _ => {}
+    }
+    let wanted = self.extension.to_lowercase();
+    if !wanted.is_empty() && !entry.is_dir && extension(&entry.path) != Some(wanted) {
+      return false;
+    }
+    self.quick.is_empty() || name.to_lowercase().contains(&self.quick.to_lowercase())
+  }
+
+  pub fn is_default(&self) -> bool {
+    *self == Self::default()
+  }
+}
+
+/// Extensions in a listing with how many files have them, for the quick
+/// filter menu.
+pub fn extensions(entries: &[DirEntry]) -> BTreeMap<String, usize> {
+  let mut extensions = BTreeMap::new();
+  for entry in entries.iter().filter(|entry| !entry.is_dir) {
+    if let Some(extension) = extension(&entry.path) {
+      *extensions.entry(extension).or_insert(0) += 1;
+    }
+  }
+  extensions
+}
+
+fn extension(path: &Path) -> Option<String> {
+  path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  fn entry(name: &str, is_dir: bool) -> DirEntry {
+    DirEntry {
+      path: PathBuf::from("/home/me").join(name),
+      name: name.into(),
+      is_dir,
+      ..DirEntry::default()
+    }
+  }
+
+  fn shown(filter: &ListingFilter) -> Vec<String> {
+    [
+      entry("notes.txt", false),
+      entry("Photo.JPG", false),
+      entry(".bashrc", false),
+      entry("draft.txt~", false),
+      entry("src", true),
+    ]
+    .iter()
+    .filter(|entry| filter.matches(entry))
+    .map(|entry| entry.display_name())
+    .collect()
+  }
+
+  #[test]
+  fn the_default_shows_everything() {
+    assert_eq!(shown(&ListingFilter::default()).len(), 5);
+  }
+
+  #[test]
+  fn hidden_backups_and_kinds() {
+    let filter = ListingFilter {
+      show_hidden: false,
+      show_backups: false,
+      ..ListingFilter::default()
+    };
+    assert_eq!(shown(&filter), vec!["notes.txt", "Photo.JPG", "src"]);
+    let filter = ListingFilter {
+      kind: Kind::Dirs,
+      ..ListingFilter::default()
+    };
+    assert_eq!(shown(&filter), vec!["src"]);
+    let filter = ListingFilter {
+      kind: Kind::Files,
+      ..ListingFilter::default()
+    };
+    assert_eq!(shown(&filter).len(), 4);
+  }
+
+  #[test]
+  fn extension_and_quick_ignore_case() {
+    let filter = ListingFilter {
+      extension: "jpg".to_owned(),
+      ..ListingFilter::default()
+    };
+    assert_eq!(shown(&filter), vec!["Photo.JPG", "src"]);
+    let filter = ListingFilter {
+      quick: "PHO".to_owned(),
+      ..ListingFilter::default()
+    };
+    assert_eq!(shown(&filter), vec!["Photo.JPG"]);
+  }
+}
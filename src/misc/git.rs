@@ -0,0 +1,256 @@
+//! Git status for the listing, read with libgit2 on a background thread
+//! whenever the location changes or the watcher reports something.
+
+use crossbeam_channel::{bounded, Receiver};
+use eframe::egui;
+use git2::{BranchType, Repository, StatusOptions};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::app::{Error, Themis};
+use crate::misc::name::path_from_bytes;
+
+/// Ordered by how much it matters, directories show their worst child.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitStatus {
+  Ignored,
+  Untracked,
+  Staged,
+  Modified,
+  Conflicted,
+}
+
+impl GitStatus {
+  pub fn badge(self) -> &'static str {
+    match self {
+      GitStatus::Ignored => "I",
+      GitStatus::Untracked => "?",
+      GitStatus::Staged => "S",
+      GitStatus::Modified => "M",
+      GitStatus::Conflicted => "!",
+    }
+  }
+
+  pub fn label(self) -> &'static str {
+    match self {
+      GitStatus::Ignored => "Ignored",
+      GitStatus::Untracked => "Untracked",
+      GitStatus::Staged => "Staged",
+      GitStatus::Modified => "Modified",
+      GitStatus::Conflicted => "Conflicted",
+    }
+  }
+
+  pub fn color(self) -> egui::Color32 {
+    match self {
+      GitStatus::Ignored => egui::Color32::GRAY,
+      GitStatus::Untracked => egui::Color32::LIGHT_BLUE,
+      GitStatus::Staged => egui::Color32::LIGHT_GREEN,
+      GitStatus::Modified => egui::Color32::GOLD,
+      GitStatus::Conflicted => egui::Color32::LIGHT_RED,
+    }
+  }
+}
+
+pub struct Repo {
+  pub workdir: PathBuf,
+  /// The branch name, or a short commit id when the head is detached.
+  pub branch: String,
+  /// Commits ahead of and behind the upstream branch, if there is one.
+  pub ahead_behind: Option<(usize, usize)>,
+  pub statuses: HashMap<PathBuf, GitStatus>,
+  /// The most important status below each directory, ignored files aside.
+  directories: HashMap<PathBuf, GitStatus>,
+}
+
+impl Repo {
+  /// The worktree containing `path`, `None` outside of one.
+  pub fn open(path: &Path) -> Result<Option<Repo>, Error> {
+    let repository = match Repository::discover(path) {
+      Ok(repository) => repository,
+      Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+      Err(err) => return Err(Error::Git(err.message().to_owned())),
+    };
+    let workdir = match repository.workdir() {
+      Some(workdir) => workdir.to_path_buf(),
+      None => return Ok(None),
+    };
+    let git = |err: git2::Error| Error::Git(err.message().to_owned());
+
+    let mut options = StatusOptions::new();
+    options
+      .include_untracked(true)
+      .include_ignored(true)
+      .recurse_untracked_dirs(false)
+      .recurse_ignored_dirs(false)
+      .exclude_submodules(true);
+    let mut statuses = HashMap::new();
+    for entry in repository.statuses(Some(&mut options)).map_err(git)?.iter() {
+      let status = match classify(entry.status()) {
+        Some(status) => status,
+        None => continue,
+      };
+      // * Untracked and ignored directories come with a trailing slash
+      let relative = entry.path_bytes();
+      let relative = relative.strip_suffix(b"/").unwrap_or(relative);
+      statuses.insert(workdir.join(path_from_bytes(relative.to_vec())), status);
+    }
+    let directories = aggregate(&workdir, &statuses);
+
+    let (branch, ahead_behind) = match repository.head() {
+      Ok(head) if head.is_branch() => {
+        let name = head.shorthand().unwrap_or("HEAD").to_owned();
+        let ahead_behind = repository
+          .find_branch(&name, BranchType::Local)
+          .and_then(|branch| branch.upstream())
+          .ok()
+          .and_then(|upstream| {
+            let local = head.target()?;
+            let upstream = upstream.get().target()?;
+            repository.graph_ahead_behind(local, upstream).ok()
+          });
+        (name, ahead_behind)
+      }
+      Ok(head) => {
+        let id = head.target().map(|id| id.to_string()).unwrap_or_default();
+        (format!("detached at {}", &id[..id.len().min(7)]), None)
+      }
+      // * A fresh repository without commits has no head yet
+      Err(_) => ("no commits yet".to_owned(), None),
+    };
+
+    Ok(Some(Repo {
+      workdir,
+      branch,
+      ahead_behind,
+      statuses,
+      directories,
+    }))
+  }
+
+  /// A file's own status, a directory's is the most important one below it.
+  pub fn status_of(&self, path: &Path, is_dir: bool) -> Option<GitStatus> {
+    let own = self.statuses.get(path).copied();
+    if !is_dir || own.is_some() {
+      return own;
+    }
+    self.directories.get(path).copied()
+  }
+}
+
+/// Hands every status that isn't ignored up to the directories above it,
+/// as far as the worktree.
+fn aggregate(workdir: &Path, statuses: &HashMap<PathBuf, GitStatus>) -> HashMap<PathBuf, GitStatus> {
+  let mut directories: HashMap<PathBuf, GitStatus> = HashMap::new();
+  for (path, status) in statuses {
+    if *status == GitStatus::Ignored {
+      continue;
+    }
+    for parent in path.ancestors().skip(1).take_while(|parent| parent.starts_with(workdir)) {
+      let worst = directories.entry(parent.to_path_buf()).or_insert(*status);
+      *worst = (*worst).max(*status);
+    }
+  }
+  directories
+}
+
+fn classify(status: git2::Status) -> Option<GitStatus> {
+  if status.is_conflicted() {
+    Some(GitStatus::Conflicted)
+  } else if status.is_wt_modified() || status.is_wt_deleted() || status.is_wt_renamed() || status.is_wt_typechange() {
+    Some(GitStatus::Modified)
+  } else if status.is_index_new()
+    || status.is_index_modified()
+    || status.is_index_deleted()
+    || status.is_index_renamed()
+    || status.is_index_typechange()
+  {
+    Some(GitStatus::Staged)
+  } else if status.is_wt_new() {
+    Some(GitStatus::Untracked)
+  } else if status.is_ignored() {
+    Some(GitStatus::Ignored)
+  } else {
+    None
+  }
+}
+
+#[derive(Default)]
+pub struct Git {
+  pub repo: Option<Repo>,
+  receiver: Option<Receiver<Result<Option<Repo>, Error>>>,
+  /// The location the repository was last read for.
+  checked: Option<PathBuf>,
+  /// Set by the watcher, the status is read again on the next frame.
+  pub stale: bool,
+}
+
+/// Starts a read when the location changed or the watcher saw something,
+/// and annotates the listing once it's done.
+pub fn poll(state: &mut Themis, ctx: &egui::Context) {
+  if let Some(receiver) = &state.git.receiver {
+    match receiver.try_recv() {
+      Ok(result) => {
+        state.git.receiver = None;
+        match result {
+          Ok(repo) => state.git.repo = repo,
+          Err(err) => {
+            state.git.repo = None;
+            state.errors.report(err);
+          }
+        }
+        annotate(state);
+      }
+      Err(crossbeam_channel::TryRecvError::Empty) => return,
+      Err(crossbeam_channel::TryRecvError::Disconnected) => state.git.receiver = None,
+    }
+  }
+  if state.git.checked.as_ref() == Some(&state.current_path) && !state.git.stale {
+    return;
+  }
+  state.git.checked = Some(state.current_path.clone());
+  state.git.stale = false;
+
+  let (sender, receiver) = bounded(1);
+  let path = state.current_path.clone();
+  let ctx = ctx.clone();
+  thread::spawn(move || {
+    let _ = sender.send(Repo::open(&path));
+    ctx.request_repaint();
+  });
+  state.git.receiver = Some(receiver);
+}
+
+fn annotate(state: &mut Themis) {
+  let repo = state.git.repo.as_ref();
+  for entry in state.dir_entries.iter_mut().chain(state.search_results.iter_mut()) {
+    entry.git = repo.and_then(|repo| repo.status_of(&entry.path, entry.is_dir));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn directories_show_their_worst_child() {
+    let workdir = Path::new("/repo");
+    let statuses: HashMap<PathBuf, GitStatus> = [
+      ("/repo/src/a.rs", GitStatus::Staged),
+      ("/repo/src/ui/b.rs", GitStatus::Modified),
+      ("/repo/target", GitStatus::Ignored),
+      ("/repo/docs/new", GitStatus::Untracked),
+    ]
+    .iter()
+    .map(|(path, status)| (PathBuf::from(path), *status))
+    .collect();
+    let directories = aggregate(workdir, &statuses);
+    assert_eq!(directories.get(Path::new("/repo/src")), Some(&GitStatus::Modified));
+    assert_eq!(directories.get(Path::new("/repo/src/ui")), Some(&GitStatus::Modified));
+    assert_eq!(directories.get(Path::new("/repo/docs")), Some(&GitStatus::Untracked));
+    assert_eq!(directories.get(Path::new("/repo")), Some(&GitStatus::Modified));
+    assert_eq!(directories.get(Path::new("/")), None);
+    assert_eq!(directories.len(), 4);
+  }
+}
@@ -0,0 +1,114 @@
+//! The saved file index. It is `mft_ntfs::Filesystem`, whose paths are
+//! `String`s; that type belongs to the MFT reader, so the index stays keyed
+//! by UTF-8 and names that aren't valid UTF-8 are left out of it. Listings
+//! read their size from the disk instead, and `refresh` counts what was
+//! left out so the command line can say so.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::app::Error;
+use crate::misc::excludes::Excludes;
+use crate::ui::settings::SearchSettings;
+
+/// Reads a saved index, `None` when there is none yet.
+pub fn load(path: &Path) -> Result<Option<mft_ntfs::Filesystem>, Error> {
+  let unserialised = match std::fs::read(path) {
+    Ok(unserialised) => unserialised,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(Error::io("read", path, err)),
+  };
+  bincode::deserialize(&unserialised)
+    .map(Some)
+    .map_err(|err| Error::Index(err.to_string()))
+}
+
+pub fn save(path: &Path, filesystem: &mft_ntfs::Filesystem) -> Result<(), Error> {
+  let serialised = bincode::serialize(filesystem).map_err(|err| Error::Index(err.to_string()))?;
+  std::fs::write(path, &serialised).map_err(|err| Error::io("write", path, err))
+}
+
+/// Drops what the ignore files and exclude globs leave out. Directories are
+/// not known at this point, so an ignored directory itself stays while
+/// everything inside it goes.
+pub fn exclude(filesystem: &mut mft_ntfs::Filesystem, settings: &SearchSettings) {
+  let mut excludes = Excludes::for_index(settings);
+  if excludes.is_active() {
+    filesystem
+      .files
+      .retain(|key, _| !excludes.is_excluded(Path::new(key), false));
+  }
+}
+
+/// Brings the entries below `root` up to date from the disk: sizes are
+/// read again and whatever is gone is dropped. Entries can only come from
+/// the MFT reader, so this gives up and returns `None` as soon as it finds
+/// something the index doesn't have yet. Otherwise it returns how many
+/// paths could not be indexed for not being UTF-8.
+pub fn refresh(
+  filesystem: &mut mft_ntfs::Filesystem,
+  root: &Path,
+  settings: &SearchSettings,
+) -> Option<usize> {
+  let mut excludes = Excludes::for_index(settings);
+  let mut seen = HashSet::new();
+  let mut left_out = 0;
+  let mut pending = vec![root.to_path_buf()];
+  while let Some(path) = pending.pop() {
+    let metadata = match std::fs::symlink_metadata(&path) {
+      Ok(metadata) => metadata,
+      Err(_) => continue,
+    };
+    if excludes.is_active() && excludes.is_excluded(&path, metadata.is_dir()) {
+      continue;
+    }
+    // * The index is keyed by UTF-8 paths, anything else isn't in it
+    match path.to_str() {
+      Some(key) => {
+        match filesystem.files.get_mut(key) {
+          Some(file) if !metadata.is_dir() => file.real_size = metadata.len(),
+          Some(_) => {}
+          None => return None,
+        }
+        seen.insert(key.to_owned());
+      }
+      None => left_out += 1,
+    }
+    if metadata.is_dir() {
+      if let Ok(dir) = std::fs::read_dir(&path) {
+        pending.extend(dir.flatten().map(|entry| entry.path()));
+      }
+    }
+  }
+  filesystem
+    .files
+    .retain(|key, _| !Path::new(key).starts_with(root) || seen.contains(key));
+  Some(left_out)
+}
+
+/// Scans the drives from scratch.
+pub fn build() -> Result<mft_ntfs::Filesystem, Error> {
+  mft_ntfs::main(None).map_err(|err| Error::Index(format!("{:?}", err)))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+  use std::ffi::OsStr;
+  use std::os::unix::ffi::OsStrExt;
+
+  #[test]
+  fn refresh_counts_what_it_cannot_index() {
+    let scratch = scratch();
+    let root = scratch.path().join(OsStr::from_bytes(b"caf\xE9"));
+    std::fs::create_dir(&root).unwrap();
+    std::fs::write(root.join(OsStr::from_bytes(b"r\xE9sum\xE9.txt")), "").unwrap();
+    let mut filesystem = mft_ntfs::Filesystem::new();
+    let settings = SearchSettings::default();
+    assert_eq!(refresh(&mut filesystem, &root, &settings), Some(2));
+    // * A UTF-8 path the index doesn't have needs a new scan
+    std::fs::write(scratch.path().join("new.txt"), "").unwrap();
+    assert_eq!(refresh(&mut filesystem, scratch.path(), &settings), None);
+  }
+}
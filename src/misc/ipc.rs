@@ -0,0 +1,319 @@
+//! Single instance support. The first window listens on a Unix socket and
+//! later launches hand their path to it instead of opening a second window.
+//!
+//! The protocol is one JSON object per line, answered with `{"ok":true}` or
+//! `{"ok":false,"error":"..."}`:
+//!
+//! ```text
+//! {"command":"open","path":"/home/me/Documents"}
+//! {"command":"reveal-and-select","path":"/home/me/notes.txt"}
+//! {"command":"show-properties","path":"/home/me/notes.txt"}
+//! {"command":"search","query":"invoice"}
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::app::{PanelOpen, Themis};
+use crate::misc::properties;
+use crate::misc::search::{update_current_dir, update_search};
+
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+  Open { path: PathBuf },
+  RevealAndSelect { path: PathBuf },
+  ShowProperties { path: PathBuf },
+  Search { query: String },
+}
+
+impl Command {
+  /// Opens directories and reveals anything else in its directory.
+  pub fn for_path(path: &Path) -> Self {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if path.is_dir() {
+      Command::Open { path }
+    } else {
+      Command::RevealAndSelect { path }
+    }
+  }
+}
+
+/// Acts on a command, from the socket or from the command line.
+pub fn apply(state: &mut Themis, command: Command) {
+  state.panel_open = PanelOpen::Main;
+  match command {
+    Command::Open { path } => state.current_path = path,
+    Command::RevealAndSelect { path } => {
+      if let Some(parent) = path.parent() {
+        state.current_path = parent.to_path_buf();
+      }
+      // * List the directory right away, entering it on the next frame
+      // * would clear the selection again
+      state.search.clear();
+      if let Err(err) = update_current_dir(state) {
+        state.errors.report(err);
+      }
+      state.selection.select(&path);
+      state.selected_path = path;
+    }
+    Command::ShowProperties { path } => properties::open(state, path),
+    Command::Search { query } => {
+      state.search = query;
+      let result = if state.search.is_empty() {
+        update_current_dir(state)
+      } else {
+        update_search(state)
+      };
+      if let Err(err) = result {
+        state.errors.report(err);
+      }
+    }
+  }
+}
+
+/// Handles everything that came in over the socket since the last frame.
+pub fn poll(state: &mut Themis) {
+  let commands: Vec<Command> = state.ipc_receiver.try_iter().collect();
+  for command in commands {
+    apply(state, command);
+  }
+}
+
+pub fn socket_path() -> PathBuf {
+  match std::env::var_os("XDG_RUNTIME_DIR") {
+    Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("themis.sock"),
+    _ => {
+      let user = std::env::var("USER").unwrap_or_default();
+      std::env::temp_dir().join(format!("themis-{}.sock", user))
+    }
+  }
+}
+
+#[cfg(unix)]
+pub use unix::{forward, serve, stop};
+
+#[cfg(not(unix))]
+pub use other::{forward, serve, stop};
+
+#[cfg(not(unix))]
+mod other {
+  use super::Command;
+  use crate::app::Error;
+  use crossbeam_channel::Sender;
+  use eframe::egui;
+
+  pub fn forward(_command: &Command) -> bool {
+    false
+  }
+
+  pub fn serve(_ctx: egui::Context, _sender: Sender<Command>, _errors: Sender<Error>) -> bool {
+    false
+  }
+
+  pub fn stop() {}
+}
+
+#[cfg(unix)]
+mod unix {
+  use super::{socket_path, Command};
+  use crate::app::Error;
+  use crossbeam_channel::Sender;
+  use eframe::egui;
+  use std::io::{BufRead, BufReader, Write};
+  use std::os::unix::net::{UnixListener, UnixStream};
+  use std::thread;
+
+  /// Sends `command` to a running window, false when there is none.
+  pub fn forward(command: &Command) -> bool {
+    let mut stream = match UnixStream::connect(socket_path()) {
+      Ok(stream) => stream,
+      Err(_) => return false,
+    };
+    let line = match serde_json::to_string(command) {
+      Ok(line) => line,
+      Err(_) => return false,
+    };
+    if writeln!(stream, "{}", line).is_err() {
+      return false;
+    }
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).is_ok() && reply.contains("\"ok\":true")
+  }
+
+  /// Starts listening and passing commands to `sender`, unless another
+  /// window already is.
+  pub fn serve(ctx: egui::Context, sender: Sender<Command>, errors: Sender<Error>) -> bool {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+      return false;
+    }
+    // * Nobody answered, so whatever is left there is stale
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+      Ok(listener) => listener,
+      Err(err) => {
+        let _ = errors.send(Error::io("listen on", &path, err));
+        return false;
+      }
+    };
+    thread::spawn(move || {
+      for stream in listener.incoming().flatten() {
+        let sender = sender.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || handle(stream, &sender, &ctx));
+      }
+    });
+    true
+  }
+
+  fn handle(stream: UnixStream, sender: &Sender<Command>, ctx: &egui::Context) {
+    let mut writer = match stream.try_clone() {
+      Ok(writer) => writer,
+      Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+      let line = match line {
+        Ok(line) => line,
+        Err(_) => return,
+      };
+      let reply = match serde_json::from_str::<Command>(&line) {
+        Ok(command) => {
+          let _ = sender.send(command);
+          ctx.request_repaint();
+          serde_json::json!({ "ok": true })
+        }
+        Err(err) => serde_json::json!({ "ok": false, "error": err.to_string() }),
+      };
+      if writeln!(writer, "{}", reply).is_err() {
+        return;
+      }
+    }
+  }
+
+  /// Removes the socket so the next launch starts fresh.
+  pub fn stop() {
+    let _ = std::fs::remove_file(socket_path());
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn bad_lines_are_answered_and_the_connection_stays_up() {
+      let (client, server) = UnixStream::pair().unwrap();
+      let (sender, receiver) = crossbeam_channel::unbounded();
+      let listener = thread::spawn(move || handle(server, &sender, &egui::Context::default()));
+
+      let mut writer = client.try_clone().unwrap();
+      let mut replies = BufReader::new(client);
+      let mut reply = String::new();
+      for line in ["not json", r#"{"command":"delete","path":"/"}"#, r#"{"command":"open"}"#] {
+        writeln!(writer, "{}", line).unwrap();
+        reply.clear();
+        replies.read_line(&mut reply).unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["ok"], false, "{}", line);
+        assert!(reply["error"].is_string());
+      }
+
+      writeln!(writer, r#"{{"command":"open","path":"/tmp"}}"#).unwrap();
+      reply.clear();
+      replies.read_line(&mut reply).unwrap();
+      assert_eq!(reply.trim(), r#"{"ok":true}"#);
+      assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        vec![Command::Open {
+          path: PathBuf::from("/tmp")
+        }]
+      );
+
+      // * Hanging up ends the handler
+      drop(writer);
+      drop(replies);
+      listener.join().unwrap();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  #[test]
+  fn commands_round_trip_as_json() {
+    let commands = [
+      (
+        Command::Open {
+          path: PathBuf::from("/home/me/Documents"),
+        },
+        r#"{"command":"open","path":"/home/me/Documents"}"#,
+      ),
+      (
+        Command::RevealAndSelect {
+          path: PathBuf::from("/home/me/notes.txt"),
+        },
+        r#"{"command":"reveal-and-select","path":"/home/me/notes.txt"}"#,
+      ),
+      (
+        Command::ShowProperties {
+          path: PathBuf::from("/home/me/notes.txt"),
+        },
+        r#"{"command":"show-properties","path":"/home/me/notes.txt"}"#,
+      ),
+      (
+        Command::Search {
+          query: String::from("invoice"),
+        },
+        r#"{"command":"search","query":"invoice"}"#,
+      ),
+    ];
+    for (command, line) in commands {
+      assert_eq!(serde_json::to_string(&command).unwrap(), line);
+      assert_eq!(serde_json::from_str::<Command>(line).unwrap(), command);
+    }
+  }
+
+  #[test]
+  fn directories_open_and_files_are_revealed() {
+    let scratch = scratch();
+    let dir = scratch.path().canonicalize().unwrap();
+    let file = dir.join("notes.txt");
+    std::fs::write(&file, "").unwrap();
+    match Command::for_path(&dir) {
+      Command::Open { path } => assert_eq!(path, dir),
+      other => panic!("expected open, got {:?}", other),
+    }
+    match Command::for_path(&dir.join(".").join("notes.txt")) {
+      Command::RevealAndSelect { path } => assert_eq!(path, file),
+      other => panic!("expected a reveal, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn revealing_selects_the_file_in_its_directory() {
+    let scratch = scratch();
+    let dir = scratch.path().canonicalize().unwrap();
+    let file = dir.join("notes.txt");
+    std::fs::write(&file, "").unwrap();
+    let mut state = Themis::default();
+    let (updater, _updates) = crossbeam_channel::unbounded();
+    state.dir_watcher.watcher_updater = updater;
+    state.search = String::from("invoice");
+
+    apply(&mut state, Command::RevealAndSelect { path: file.clone() });
+    assert_eq!(state.current_path, dir);
+    assert_eq!(state.last_path, dir);
+    assert!(state.search.is_empty());
+    assert!(state.dir_entries.iter().any(|entry| entry.path == file));
+    assert_eq!(state.selected_path, file);
+    assert!(state.selection.contains(&file));
+    assert!(state.errors.entries.is_empty());
+
+    // * The next frame sees nothing left to navigate, the selection stays
+    update_current_dir(&mut state).unwrap();
+    assert!(state.selection.contains(&file));
+  }
+}
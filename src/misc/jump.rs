@@ -0,0 +1,234 @@
+//! Frecency ranked directory visits for the jump dialog, scored the way
+//! zoxide does it: a rank that grows with every visit, weighted by how long
+//! ago the last one was.
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::Error;
+use crate::misc::name::{path_bytes, path_from_bytes};
+
+/// Once the ranks add up to more than this, all of them are scaled down and
+/// the ones that drop below a single visit are forgotten.
+const MAX_TOTAL_RANK: f64 = 10_000.0;
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+/// Visits older than this are forgotten. Directories that are only missing
+/// for a while, like those on an unmounted drive, are kept until then.
+const MAX_AGE: u64 = 90 * DAY;
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Visit {
+  pub rank: f64,
+  /// Seconds since the epoch.
+  pub last: u64,
+}
+
+#[derive(Default)]
+pub struct Jump {
+  pub visits: HashMap<PathBuf, Visit>,
+  pub file: Option<PathBuf>,
+  pub open: bool,
+  pub query: String,
+  pub selected: usize,
+}
+
+impl Jump {
+  /// Reads `visits.bin` from the save location, a broken file starts over.
+  /// Paths are stored as raw bytes, serde only takes paths that are UTF-8.
+  pub fn load(location: &Path) -> Result<Self, Error> {
+    let file = location.join("visits.bin");
+    let visits = match std::fs::read(&file) {
+      Ok(bytes) => bincode::deserialize::<HashMap<Vec<u8>, Visit>>(&bytes)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(path, visit)| (path_from_bytes(path), visit))
+        .collect(),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+      Err(err) => return Err(Error::io("read", &file, err)),
+    };
+    let mut jump = Self {
+      visits,
+      file: Some(file),
+      ..Self::default()
+    };
+    jump.forget_old(now());
+    Ok(jump)
+  }
+
+  pub fn save(&self) -> Result<(), Error> {
+    let file = match &self.file {
+      Some(file) => file,
+      None => return Ok(()),
+    };
+    let visits: HashMap<Vec<u8>, &Visit> =
+      self.visits.iter().map(|(path, visit)| (path_bytes(path), visit)).collect();
+    let serialised = bincode::serialize(&visits).map_err(|err| Error::Jump(err.to_string()))?;
+    std::fs::write(file, &serialised).map_err(|err| Error::io("write", file, err))
+  }
+
+  pub fn record(&mut self, path: &Path) {
+    let now = now();
+    let visit = self.visits.entry(path.to_path_buf()).or_insert(Visit { rank: 0.0, last: now });
+    visit.rank += 1.0;
+    visit.last = now;
+    if self.visits.values().map(|visit| visit.rank).sum::<f64>() > MAX_TOTAL_RANK {
+      for visit in self.visits.values_mut() {
+        visit.rank *= 0.9;
+      }
+      self.visits.retain(|_, visit| visit.rank >= 1.0);
+    }
+    self.forget_old(now);
+  }
+
+  fn forget_old(&mut self, now: u64) {
+    self.visits.retain(|_, visit| now.saturating_sub(visit.last) < MAX_AGE);
+  }
+
+  /// Directories matching every word of the query, best first. Each word
+  /// fuzzy matches a path component after the one the previous word
+  /// matched, and the last word has to match the final component, so
+  /// `thm src` finds `~/code/themis/src`. Only the best `limit` are
+  /// checked to still be directories.
+  pub fn matches(&self, query: &str, limit: usize) -> Vec<(PathBuf, f64)> {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let now = now();
+    let mut matches: Vec<(PathBuf, f64)> = self
+      .visits
+      .iter()
+      .filter(|(path, _)| words_match(&matcher, &words, path))
+      .map(|(path, visit)| (path.clone(), score(visit, now)))
+      .collect();
+    matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    matches
+      .into_iter()
+      .filter(|(path, _)| path.is_dir())
+      .take(limit)
+      .collect()
+  }
+}
+
+fn words_match(matcher: &SkimMatcherV2, words: &[&str], path: &Path) -> bool {
+  let components: Vec<String> = path
+    .components()
+    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+    .collect();
+  let mut next = 0;
+  for (index, word) in words.iter().enumerate() {
+    let last = index + 1 == words.len();
+    let found = if last {
+      // * The last word is for the directory we end up in
+      match components.last() {
+        Some(component) if next < components.len() => {
+          matcher.fuzzy_match(component, word).is_some()
+        }
+        _ => false,
+      }
+    } else {
+      match components[next..]
+        .iter()
+        .position(|component| matcher.fuzzy_match(component, word).is_some())
+      {
+        Some(position) => {
+          next += position + 1;
+          true
+        }
+        None => false,
+      }
+    };
+    if !found {
+      return false;
+    }
+  }
+  true
+}
+
+fn score(visit: &Visit, now: u64) -> f64 {
+  let age = now.saturating_sub(visit.last);
+  let weight = if age < HOUR {
+    4.0
+  } else if age < DAY {
+    2.0
+  } else if age < WEEK {
+    0.5
+  } else {
+    0.25
+  };
+  visit.rank * weight
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  fn words_match_path(query: &str, path: &str) -> bool {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    words_match(&SkimMatcherV2::default().ignore_case(), &words, Path::new(path))
+  }
+
+  #[test]
+  fn words_match_components_in_order() {
+    assert!(words_match_path("thm src", "/home/me/code/themis/src"));
+    assert!(words_match_path("code src", "/home/me/code/themis/src"));
+    assert!(!words_match_path("src thm", "/home/me/code/themis/src"));
+    // * Two words can't share a component
+    assert!(!words_match_path("src src", "/home/me/code/themis/src"));
+  }
+
+  #[test]
+  fn the_last_word_matches_the_last_component() {
+    assert!(words_match_path("THEMIS", "/home/me/code/themis"));
+    assert!(!words_match_path("code", "/home/me/code/themis"));
+    assert!(words_match_path("", "/home/me/code/themis"));
+  }
+
+  #[test]
+  fn old_visits_are_forgotten() {
+    let mut jump = Jump::default();
+    let now = now();
+    jump.visits.insert(PathBuf::from("/old"), Visit { rank: 50.0, last: now - MAX_AGE });
+    jump.visits.insert(PathBuf::from("/recent"), Visit { rank: 1.0, last: now - WEEK });
+    jump.record(Path::new("/new"));
+    let mut kept: Vec<&PathBuf> = jump.visits.keys().collect();
+    kept.sort();
+    assert_eq!(kept, vec![Path::new("/new"), Path::new("/recent")]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn visits_to_any_path_are_saved() {
+    use std::os::unix::ffi::OsStrExt;
+    let scratch = scratch();
+    let visited = Path::new("/home/me").join(std::ffi::OsStr::from_bytes(b"caf\xE9"));
+    let mut jump = Jump::load(scratch.path()).unwrap();
+    jump.record(&visited);
+    jump.record(Path::new("/home/me/code"));
+    jump.save().unwrap();
+    let loaded = Jump::load(scratch.path()).unwrap();
+    assert_eq!(loaded.visits.len(), 2);
+    assert_eq!(loaded.visits[&visited].rank, 1.0);
+  }
+
+  #[test]
+  fn visits_saved_with_utf8_paths_still_load() {
+    let scratch = scratch();
+    let mut old = HashMap::new();
+    old.insert(PathBuf::from("/home/me/code"), Visit { rank: 3.0, last: now() });
+    std::fs::write(scratch.path().join("visits.bin"), bincode::serialize(&old).unwrap()).unwrap();
+    let loaded = Jump::load(scratch.path()).unwrap();
+    assert_eq!(loaded.visits[Path::new("/home/me/code")].rank, 3.0);
+  }
+}
@@ -0,0 +1,220 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Sniffed types kept at most, the cache starts over once it's full.
+const SNIFFED_LIMIT: usize = 4096;
+
+/// What kind of file an entry is, for icons and the details column.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileType {
+  pub mime: String,
+  /// Human-readable, like "PNG image".
+  pub description: String,
+  pub icon: &'static str,
+}
+
+/// Looks types up in the shared-mime-info database, caching descriptions
+/// as they are first needed.
+#[derive(Default)]
+pub struct MimeDb {
+  /// Generic icon names by MIME type, from `generic-icons`.
+  generic_icons: HashMap<String, String>,
+  descriptions: RefCell<HashMap<String, String>>,
+  /// Sniffed types with the modification time they were read at.
+  sniffed: RefCell<HashMap<PathBuf, (Option<SystemTime>, String)>>,
+}
+
+impl MimeDb {
+  pub fn load() -> Self {
+    let mut generic_icons = HashMap::new();
+    // * Earlier directories take precedence, so read them last
+    for dir in data_dirs().iter().rev() {
+      if let Ok(contents) = fs::read_to_string(dir.join("mime/generic-icons")) {
+        for line in contents.lines() {
+          if let Some((mime, icon)) = line.split_once(':') {
+            generic_icons.insert(mime.to_owned(), icon.to_owned());
+          }
+        }
+      }
+    }
+    Self {
+      generic_icons,
+      descriptions: RefCell::default(),
+      sniffed: RefCell::default(),
+    }
+  }
+
+  /// Goes by the extension, cheap enough for every listed entry. Files
+  /// whose extension says nothing, like `Makefile`, are sniffed instead.
+  pub fn guess(&self, path: &Path, is_dir: bool, is_empty: bool) -> FileType {
+    if is_dir {
+      return folder(is_empty);
+    }
+    match mime_guess::from_path(path).first() {
+      Some(guess) => self.file_type(guess.essence_str().to_owned()),
+      None => self.detect(path, is_dir, is_empty),
+    }
+  }
+
+  /// Sniffs the first bytes of `path`, falling back to its extension when
+  /// the contents only tell us it is some text or binary. The answer is
+  /// kept until the file is modified.
+  pub fn detect(&self, path: &Path, is_dir: bool, is_empty: bool) -> FileType {
+    if is_dir {
+      return folder(is_empty);
+    }
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    if let Some((when, mime)) = self.sniffed.borrow().get(path) {
+      if *when == modified {
+        return self.file_type(mime.clone());
+      }
+    }
+    let mut mime = tree_magic_mini::from_filepath(path)
+      .unwrap_or("application/octet-stream")
+      .to_owned();
+    if mime == "application/octet-stream" || mime == "text/plain" {
+      if let Some(guess) = mime_guess::from_path(path).first() {
+        mime = guess.essence_str().to_owned();
+      }
+    }
+    let mut sniffed = self.sniffed.borrow_mut();
+    if sniffed.len() >= SNIFFED_LIMIT {
+      sniffed.clear();
+    }
+    sniffed.insert(path.to_path_buf(), (modified, mime.clone()));
+    self.file_type(mime)
+  }
+
+  fn file_type(&self, mime: String) -> FileType {
+    FileType {
+      description: self.description(&mime),
+      icon: self.icon(&mime),
+      mime,
+    }
+  }
+
+  fn description(&self, mime: &str) -> String {
+    if let Some(description) = self.descriptions.borrow().get(mime) {
+      return description.clone();
+    }
+    let description = data_dirs()
+      .iter()
+      .find_map(|dir| fs::read_to_string(dir.join("mime").join(format!("{}.xml", mime))).ok())
+      .and_then(|xml| comment(&xml))
+      .unwrap_or_else(|| mime.to_owned());
+    self
+      .descriptions
+      .borrow_mut()
+      .insert(mime.to_owned(), description.clone());
+    description
+  }
+
+  fn icon(&self, mime: &str) -> &'static str {
+    let media = mime.split('/').next().unwrap_or_default();
+    let generic = self
+      .generic_icons
+      .get(mime)
+      .cloned()
+      .unwrap_or_else(|| format!("{}-x-generic", media));
+    match generic.as_str() {
+      "image-x-generic" => "🖼",
+      "audio-x-generic" => "🎵",
+      "video-x-generic" => "🎞",
+      "font-x-generic" => "🗛",
+      "package-x-generic" => "📦",
+      "text-x-script" => "📜",
+      "application-x-executable" => "⚙",
+      "x-office-document" => "🗎",
+      "x-office-spreadsheet" => "🗠",
+      "x-office-presentation" => "📽",
+      "text-html" => "🌐",
+      "text-x-generic" => "🗒",
+      _ => "🗋",
+    }
+  }
+}
+
+fn folder(is_empty: bool) -> FileType {
+  FileType {
+    mime: "inode/directory".to_owned(),
+    description: "Folder".to_owned(),
+    icon: if is_empty { "🗁" } else { "🗀" },
+  }
+}
+
+/// The untranslated `<comment>` of a shared-mime-info type file.
+fn comment(xml: &str) -> Option<String> {
+  let start = xml.find("<comment>")? + "<comment>".len();
+  let end = start + xml[start..].find("</comment>")?;
+  Some(
+    xml[start..end]
+      .replace("&lt;", "<")
+      .replace("&gt;", ">")
+      .replace("&quot;", "\"")
+      .replace("&apos;", "'")
+      .replace("&amp;", "&"),
+  )
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, with the spec's defaults.
+pub fn data_dirs() -> Vec<PathBuf> {
+  let mut dirs = Vec::new();
+  match env::var_os("XDG_DATA_HOME") {
+    Some(home) if !home.is_empty() => dirs.push(PathBuf::from(home)),
+    _ => {
+      if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".local/share"));
+      }
+    }
+  }
+  let system = env::var("XDG_DATA_DIRS")
+    .ok()
+    .filter(|system| !system.is_empty())
+    .unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+  dirs.extend(system.split(':').map(PathBuf::from));
+  dirs
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0";
+
+  #[test]
+  fn guess_goes_by_the_extension() {
+    let mime = MimeDb::default();
+    // * Nothing is read, these don't exist
+    assert_eq!(mime.guess(Path::new("/nowhere/a.png"), false, false).mime, "image/png");
+    assert_eq!(mime.guess(Path::new("/nowhere/A.JPG"), false, false).mime, "image/jpeg");
+    assert_eq!(mime.guess(Path::new("/nowhere/src"), true, false).mime, "inode/directory");
+    assert!(mime.sniffed.borrow().is_empty());
+  }
+
+  #[test]
+  fn contents_beat_a_missing_or_wrong_extension() {
+    let scratch = scratch();
+    let mime = MimeDb::default();
+    let picture = scratch.path().join("picture");
+    fs::write(&picture, PNG).unwrap();
+    assert_eq!(mime.guess(&picture, false, false).mime, "image/png");
+    let renamed = scratch.path().join("picture.txt");
+    fs::write(&renamed, PNG).unwrap();
+    assert_eq!(mime.guess(&renamed, false, false).mime, "text/plain");
+    assert_eq!(mime.detect(&renamed, false, false).mime, "image/png");
+  }
+
+  #[test]
+  fn the_sniffed_cache_is_bounded() {
+    let mime = MimeDb::default();
+    for index in 0..SNIFFED_LIMIT + 10 {
+      mime.detect(Path::new(&format!("/nowhere/{}", index)), false, false);
+    }
+    assert!(mime.sniffed.borrow().len() <= SNIFFED_LIMIT);
+  }
+}
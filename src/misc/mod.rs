@@ -1,2 +1,33 @@
+pub mod actions;
+pub mod ansi;
+pub mod bookmarks;
+pub mod cleanup;
+pub mod conflict;
+pub mod dir_sizes;
+pub mod disk_usage;
+pub mod duplicates;
+pub mod excludes;
+pub mod filters;
+#[cfg(target_os = "linux")]
+pub mod file_manager;
 pub mod fonts;
-pub mod search;
\ No newline at end of file
+pub mod git;
+pub mod index;
+pub mod ipc;
+pub mod jump;
+pub mod mime;
+pub mod name;
+pub mod open_with;
+pub mod output;
+pub mod places;
+pub mod properties;
+pub mod search;
+pub mod selection;
+pub mod tags;
+pub mod views;
+pub mod watch;
+/// A scratch directory for tests, removed again when it's dropped.
+#[cfg(test)]
+pub fn scratch() -> tempfile::TempDir {
+  tempfile::Builder::new().prefix("themis-").tempdir().unwrap()
+}
@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// Turns a file name into something printable without losing information:
+/// valid UTF-8 is kept as is and every invalid byte is written as `\xNN`.
+/// On unix a literal backslash becomes `\\`, so `unescape` can tell the two
+/// apart; elsewhere it is the path separator and left alone.
+pub fn escape(name: &OsStr) -> String {
+  let bytes = raw_bytes(name);
+  let mut escaped = String::with_capacity(bytes.len());
+  let mut rest: &[u8] = &bytes;
+  loop {
+    match std::str::from_utf8(rest) {
+      Ok(valid) => {
+        push_valid(&mut escaped, valid);
+        return escaped;
+      }
+      Err(err) => {
+        let (valid, invalid) = rest.split_at(err.valid_up_to());
+        push_valid(&mut escaped, &String::from_utf8_lossy(valid));
+        let invalid_len = err.error_len().unwrap_or(invalid.len());
+        for byte in &invalid[..invalid_len] {
+          escaped.push_str(&format!("\\x{:02X}", byte));
+        }
+        rest = &invalid[invalid_len..];
+      }
+    }
+  }
+}
+
+/// The name `escape` was given back, for text the user may have edited.
+/// A backslash that doesn't start a valid escape is kept as it is.
+#[cfg(unix)]
+pub fn unescape(text: &str) -> OsString {
+  use std::os::unix::ffi::OsStringExt;
+  let bytes = text.as_bytes();
+  let mut name = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    if bytes[index] == b'\\' {
+      if bytes.get(index + 1) == Some(&b'\\') {
+        name.push(b'\\');
+        index += 2;
+        continue;
+      }
+      let hex = text.get(index + 2..index + 4).filter(|_| bytes.get(index + 1) == Some(&b'x'));
+      if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+        name.push(byte);
+        index += 4;
+        continue;
+      }
+    }
+    name.push(bytes[index]);
+    index += 1;
+  }
+  OsString::from_vec(name)
+}
+
+// * Nothing is escaped on Windows that could be turned back into a name
+#[cfg(not(unix))]
+pub fn unescape(text: &str) -> OsString {
+  OsString::from(text)
+}
+
+/// The bytes of `path` as the OS has them, for storing it without loss.
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+  raw_bytes(path.as_os_str()).into_owned()
+}
+
+/// Turns bytes from `path_bytes` back into a path.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+  use std::os::unix::ffi::OsStringExt;
+  PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+  PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// For `#[serde(with = "...")]` on saved paths. Serde refuses paths that
+/// aren't UTF-8, so those are written as their bytes; UTF-8 paths stay
+/// plain strings, which is also what older saves have.
+pub mod saved_path {
+  use serde::{Deserialize, Deserializer, Serializer};
+  use std::path::{Path, PathBuf};
+
+  pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    match path.to_str() {
+      Some(text) => serializer.serialize_str(text),
+      None => serializer.collect_seq(super::path_bytes(path)),
+    }
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Saved {
+      Text(String),
+      Bytes(Vec<u8>),
+    }
+    Ok(match Saved::deserialize(deserializer)? {
+      Saved::Text(text) => PathBuf::from(text),
+      Saved::Bytes(bytes) => super::path_from_bytes(bytes),
+    })
+  }
+}
+
+fn push_valid(escaped: &mut String, valid: &str) {
+  if cfg!(unix) {
+    escaped.push_str(&valid.replace('\\', "\\\\"));
+  } else {
+    escaped.push_str(valid);
+  }
+}
+
+#[cfg(unix)]
+fn raw_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+  use std::os::unix::ffi::OsStrExt;
+  Cow::Borrowed(name.as_bytes())
+}
+
+// * Windows names are UTF-16, unpaired surrogates end up as U+FFFD here
+#[cfg(not(unix))]
+fn raw_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+  match name.to_string_lossy() {
+    Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
+    Cow::Owned(name) => Cow::Owned(name.into_bytes()),
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::os::unix::ffi::OsStrExt;
+
+  #[test]
+  fn valid_names_are_kept() {
+    assert_eq!(escape(OsStr::new("café.txt")), "café.txt");
+    assert_eq!(unescape("café.txt"), OsStr::new("café.txt"));
+  }
+
+  #[test]
+  fn invalid_bytes_round_trip() {
+    let name = OsStr::from_bytes(b"caf\xE9.txt");
+    assert_eq!(escape(name), "caf\\xE9.txt");
+    assert_eq!(unescape(&escape(name)), name);
+  }
+
+  #[test]
+  fn backslashes_are_not_mistaken_for_bytes() {
+    let literal = OsStr::new("a\\xFF");
+    let byte = OsStr::from_bytes(b"a\xFF");
+    assert_eq!(escape(literal), "a\\\\xFF");
+    assert_ne!(escape(literal), escape(byte));
+    assert_eq!(unescape(&escape(literal)), literal);
+    assert_eq!(unescape(&escape(byte)), byte);
+  }
+
+  #[test]
+  fn stray_backslashes_are_kept() {
+    assert_eq!(unescape("a\\b\\x4"), OsStr::new("a\\b\\x4"));
+  }
+
+  #[test]
+  fn saved_paths_keep_their_bytes() {
+    #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    struct Saved {
+      #[serde(with = "saved_path")]
+      path: PathBuf,
+    }
+    let odd = Saved {
+      path: PathBuf::from(OsStr::from_bytes(b"/home/caf\xE9")),
+    };
+    let json = serde_json::to_string(&odd).unwrap();
+    assert_eq!(serde_json::from_str::<Saved>(&json).unwrap(), odd);
+    // * UTF-8 paths are saved as before
+    let plain: Saved = serde_json::from_str(r#"{"path": "/home/me"}"#).unwrap();
+    assert_eq!(serde_json::to_string(&plain).unwrap(), r#"{"path":"/home/me"}"#);
+  }
+}
@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+
+use crate::app::Error;
+use crate::misc::mime::data_dirs;
+use crate::misc::name::{path_bytes, path_from_bytes};
+
+/// An application from a `.desktop` file.
+#[derive(Clone, Debug)]
+pub struct DesktopApp {
+  /// The desktop file id, like `org.gnome.TextEditor.desktop`.
+  pub id: String,
+  pub name: String,
+  pub exec: String,
+  pub terminal: bool,
+  pub mime_types: Vec<String>,
+  pub path: PathBuf,
+}
+
+/// Installed applications and the MIME associations from `mimeapps.list`.
+#[derive(Default)]
+pub struct Applications {
+  pub apps: Vec<DesktopApp>,
+  /// Default application ids by MIME type, most preferred first.
+  defaults: HashMap<String, Vec<String>>,
+  added: HashMap<String, Vec<String>>,
+  removed: HashMap<String, Vec<String>>,
+  /// Text box for running an arbitrary command from the context menu.
+  pub command_input: String,
+}
+
+impl Applications {
+  pub fn load() -> Self {
+    let mut applications = Self::default();
+    let mut seen = Vec::new();
+    for dir in data_dirs() {
+      let root = dir.join("applications");
+      collect(&root, &root, &mut seen, &mut applications.apps);
+    }
+    applications.apps.sort_by(|a, b| a.name.cmp(&b.name));
+    // * Read least important first, so later files win
+    for path in mimeapps_lists().iter().rev() {
+      if let Ok(contents) = fs::read_to_string(path) {
+        applications.read_mimeapps(&contents);
+      }
+    }
+    applications
+  }
+
+  /// Applications that can open `mime`, the default one first.
+  pub fn for_mime(&self, mime: &str) -> Vec<&DesktopApp> {
+    let removed = self.removed.get(mime);
+    let added = self.added.get(mime);
+    let mut apps: Vec<&DesktopApp> = self
+      .apps
+      .iter()
+      .filter(|app| {
+        (app.mime_types.iter().any(|declared| declared == mime)
+          || matches!(added, Some(added) if added.contains(&app.id)))
+          && !matches!(removed, Some(removed) if removed.contains(&app.id))
+      })
+      .collect();
+    if let Some(default) = self.default_for(mime) {
+      apps.retain(|app| app.id != default.id);
+      apps.insert(0, default);
+    }
+    apps
+  }
+
+  pub fn default_for(&self, mime: &str) -> Option<&DesktopApp> {
+    self
+      .defaults
+      .get(mime)?
+      .iter()
+      .find_map(|id| self.apps.iter().find(|app| &app.id == id))
+  }
+
+  /// Makes `id` the default for `mime` in the user's `mimeapps.list`.
+  pub fn set_default(&mut self, mime: &str, id: &str) -> Result<(), Error> {
+    let path = match mimeapps_lists().into_iter().next() {
+      Some(path) => path,
+      None => return Err(Error::Invalid("No configuration directory found".to_owned())),
+    };
+    let contents = match fs::read_to_string(&path) {
+      Ok(contents) => contents,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+      Err(err) => return Err(Error::io("read", &path, err)),
+    };
+    let contents = set_key(&contents, "Default Applications", mime, &format!("{};", id));
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|err| Error::io("create", parent, err))?;
+    }
+    fs::write(&path, contents).map_err(|err| Error::io("write", &path, err))?;
+    self.defaults.insert(mime.to_owned(), vec![id.to_owned()]);
+    Ok(())
+  }
+
+  fn read_mimeapps(&mut self, contents: &str) {
+    let mut section = "";
+    for line in contents.lines().map(str::trim) {
+      if line.starts_with('[') && line.ends_with(']') {
+        section = &line[1..line.len() - 1];
+        continue;
+      }
+      let (mime, ids) = match line.split_once('=') {
+        Some((mime, ids)) if !line.starts_with('#') => (mime.trim(), ids),
+        _ => continue,
+      };
+      let ids: Vec<String> = ids
+        .split(';')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_owned)
+        .collect();
+      let map = match section {
+        "Default Applications" => &mut self.defaults,
+        "Added Associations" => &mut self.added,
+        "Removed Associations" => &mut self.removed,
+        _ => continue,
+      };
+      map.insert(mime.to_owned(), ids);
+    }
+  }
+}
+
+/// Starts `app` on `paths`. Apps that take a list (`%F`, `%U`) get them all
+/// at once, the rest are started once per path.
+pub fn launch(app: &DesktopApp, paths: &[PathBuf]) -> Result<(), Error> {
+  let mut exec = app.exec.clone();
+  if app.terminal {
+    let terminal = env::var("TERMINAL").unwrap_or_else(|_| "xterm".to_owned());
+    exec = format!("{} -e {}", terminal, exec);
+  }
+  run(&exec, paths, Some(app))
+}
+
+/// Runs a command typed by the user, with the same field codes as `Exec`.
+/// Without any, the paths are appended to the end.
+pub fn run_command(command: &str, paths: &[PathBuf]) -> Result<(), Error> {
+  run(command, paths, None)
+}
+
+fn run(exec: &str, paths: &[PathBuf], app: Option<&DesktopApp>) -> Result<(), Error> {
+  let words = shell_words::split(exec)
+    .map_err(|err| Error::Invalid(format!("Could not parse \"{}\": {}", exec, err)))?;
+  let takes_list = words.iter().any(|word| word == "%F" || word == "%U");
+  let takes_one = words
+    .iter()
+    .any(|word| word.contains("%f") || word.contains("%u"));
+  let invocations: Vec<Vec<OsString>> = if takes_one && !takes_list {
+    paths
+      .iter()
+      .map(|path| expand(&words, std::slice::from_ref(path), app))
+      .collect()
+  } else {
+    let mut args = expand(&words, paths, app);
+    if !takes_list && app.is_none() {
+      args.extend(paths.iter().map(|path| path.as_os_str().to_os_string()));
+    }
+    vec![args]
+  };
+  let target = paths.first().map_or_else(PathBuf::new, PathBuf::clone);
+  for args in invocations {
+    let (program, args) = match args.split_first() {
+      Some(split) => split,
+      None => return Err(Error::Invalid("The command is empty".to_owned())),
+    };
+    let mut child = Command::new(program)
+      .args(args)
+      .spawn()
+      .map_err(|err| Error::io("open", &target, err))?;
+    // * Reap it in the background so it doesn't linger as a zombie
+    thread::spawn(move || child.wait());
+  }
+  Ok(())
+}
+
+/// Replaces the field codes in `words`, see the desktop entry spec. Paths
+/// are passed on as the OS has them.
+fn expand(words: &[String], paths: &[PathBuf], app: Option<&DesktopApp>) -> Vec<OsString> {
+  let mut args = Vec::new();
+  for word in words {
+    match word.as_str() {
+      "%F" => args.extend(paths.iter().map(|path| path.as_os_str().to_os_string())),
+      "%U" => args.extend(paths.iter().map(|path| OsString::from(uri(path)))),
+      "%i" => {}
+      _ => {
+        let mut arg = OsString::new();
+        let mut chars = word.chars();
+        while let Some(c) = chars.next() {
+          if c != '%' {
+            arg.push(c.encode_utf8(&mut [0; 4]));
+            continue;
+          }
+          match chars.next() {
+            Some('%') => arg.push("%"),
+            Some('f') => {
+              if let Some(path) = paths.first() {
+                arg.push(path);
+              }
+            }
+            Some('u') => {
+              if let Some(path) = paths.first() {
+                arg.push(uri(path));
+              }
+            }
+            Some('c') => arg.push(app.map_or("", |app| app.name.as_str())),
+            Some('k') => {
+              if let Some(app) = app {
+                arg.push(&app.path);
+              }
+            }
+            // * Deprecated and unknown codes are dropped
+            _ => {}
+          }
+        }
+        if !arg.is_empty() {
+          args.push(arg);
+        }
+      }
+    }
+  }
+  args
+}
+
+/// A `file://` URI with every byte outside the unreserved set escaped.
+pub fn uri(path: &Path) -> String {
+  let mut uri = "file://".to_owned();
+  for byte in path_bytes(path) {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+        uri.push(byte as char)
+      }
+      _ => uri.push_str(&format!("%{:02X}", byte)),
+    }
+  }
+  uri
+}
+
+/// Turns a `file://` URI into a path, undoing percent escapes. Plain paths
+/// are passed through, other schemes are ignored.
+pub fn path_from_uri(uri: &str) -> Option<PathBuf> {
+  let encoded = match uri.strip_prefix("file://") {
+    // * Skip the host part, it is either empty or `localhost`
+    Some(rest) => &rest[rest.find('/')?..],
+    None if uri.starts_with('/') => uri,
+    None => return None,
+  };
+  let bytes = encoded.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    let escaped = bytes
+      .get(index + 1..index + 3)
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match (bytes[index], escaped) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        index += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        index += 1;
+      }
+    }
+  }
+  Some(path_from_bytes(decoded))
+}
+
+fn collect(root: &Path, dir: &Path, seen: &mut Vec<String>, apps: &mut Vec<DesktopApp>) {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      collect(root, &path, seen, apps);
+      continue;
+    }
+    if !matches!(path.extension(), Some(extension) if extension == "desktop") {
+      continue;
+    }
+    // * Ids are relative paths with `/` swapped for `-`, the first one wins
+    let id = path
+      .strip_prefix(root)
+      .unwrap_or(&path)
+      .to_string_lossy()
+      .replace('/', "-");
+    if seen.contains(&id) {
+      continue;
+    }
+    seen.push(id.clone());
+    if let Some(app) = fs::read_to_string(&path)
+      .ok()
+      .and_then(|contents| parse(&contents, id, path.clone()))
+    {
+      apps.push(app);
+    }
+  }
+}
+
+fn parse(contents: &str, id: String, path: PathBuf) -> Option<DesktopApp> {
+  let mut in_entry = false;
+  let mut keys = HashMap::new();
+  for line in contents.lines().map(str::trim) {
+    if line.starts_with('[') {
+      in_entry = line == "[Desktop Entry]";
+      continue;
+    }
+    if let (true, Some((key, value))) = (in_entry, line.split_once('=')) {
+      keys.insert(key.trim(), value.trim());
+    }
+  }
+  let flag = |key: &str| keys.get(key) == Some(&"true");
+  if keys.get("Type") != Some(&"Application") || flag("NoDisplay") || flag("Hidden") {
+    return None;
+  }
+  Some(DesktopApp {
+    id,
+    name: keys.get("Name")?.to_string(),
+    exec: keys.get("Exec")?.to_string(),
+    terminal: flag("Terminal"),
+    mime_types: keys
+      .get("MimeType")
+      .map(|types| {
+        types
+          .split(';')
+          .filter(|mime| !mime.is_empty())
+          .map(str::to_owned)
+          .collect()
+      })
+      .unwrap_or_default(),
+    path,
+  })
+}
+
+/// Every `mimeapps.list` in lookup order, the user's own first.
+fn mimeapps_lists() -> Vec<PathBuf> {
+  let mut lists = Vec::new();
+  match env::var_os("XDG_CONFIG_HOME") {
+    Some(config) if !config.is_empty() => lists.push(PathBuf::from(config).join("mimeapps.list")),
+    _ => {
+      if let Some(home) = env::var_os("HOME") {
+        lists.push(PathBuf::from(home).join(".config/mimeapps.list"));
+      }
+    }
+  }
+  let config_dirs = env::var("XDG_CONFIG_DIRS")
+    .ok()
+    .filter(|dirs| !dirs.is_empty())
+    .unwrap_or_else(|| "/etc/xdg".to_owned());
+  lists.extend(config_dirs.split(':').map(|dir| Path::new(dir).join("mimeapps.list")));
+  lists.extend(
+    data_dirs()
+      .into_iter()
+      .map(|dir| dir.join("applications/mimeapps.list")),
+  );
+  lists
+}
+
+/// Sets `key` in `section` of an ini style file, adding either if missing.
+fn set_key(contents: &str, section: &str, key: &str, value: &str) -> String {
+  let header = format!("[{}]", section);
+  let mut lines: Vec<String> = contents.lines().map(str::to_owned).collect();
+  let start = match lines.iter().position(|line| line.trim() == header) {
+    Some(start) => start,
+    None => {
+      if matches!(lines.last(), Some(last) if !last.trim().is_empty()) {
+        lines.push(String::new());
+      }
+      lines.push(header);
+      lines.len() - 1
+    }
+  };
+  let end = lines[start + 1..]
+    .iter()
+    .position(|line| line.trim().starts_with('['))
+    .map_or(lines.len(), |offset| start + 1 + offset);
+  let entry = format!("{}={}", key, value);
+  match lines[start + 1..end]
+    .iter()
+    .position(|line| matches!(line.split_once('='), Some((existing, _)) if existing.trim() == key))
+  {
+    Some(offset) => lines[start + 1 + offset] = entry,
+    None => lines.insert(end, entry),
+  }
+  let mut contents = lines.join("\n");
+  contents.push('\n');
+  contents
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn lists_take_every_path() {
+    let words: Vec<String> = ["viewer", "--open", "%F"].iter().map(|word| word.to_string()).collect();
+    let paths = vec![PathBuf::from("/a b"), PathBuf::from("/c")];
+    assert_eq!(expand(&words, &paths, None), vec!["viewer", "--open", "/a b", "/c"]);
+  }
+
+  #[test]
+  fn codes_inside_words_take_the_first_path() {
+    let words = vec!["--file=%f".to_owned(), "100%%".to_owned()];
+    let paths = vec![PathBuf::from("/a"), PathBuf::from("/b")];
+    assert_eq!(expand(&words, &paths, None), vec!["--file=/a", "100%"]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn uris_keep_raw_bytes() {
+    use std::os::unix::ffi::OsStrExt;
+    let path = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/caf\xE9 \xC3\xA9"));
+    assert_eq!(uri(path), "file:///tmp/caf%E9%20%C3%A9");
+    assert_eq!(path_from_uri(&uri(path)).as_deref(), Some(path));
+  }
+}
@@ -0,0 +1,234 @@
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use eframe::egui;
+use std::ffi::{OsStr, OsString};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::app::{Error, Themis};
+use crate::misc::search::update_current_dir;
+
+pub enum OutputEvent {
+  Line(String),
+  /// The exit code, `None` when killed by a signal.
+  Exit(Option<i32>),
+}
+
+/// One command started from themis.
+pub struct Run {
+  pub title: String,
+  pub text: String,
+  /// Set once the command is done.
+  pub exit: Option<Option<i32>>,
+  /// Whether the output was captured for the panel.
+  pub show: bool,
+  /// Re-read the current directory once it is done.
+  pub refresh: bool,
+  receiver: Receiver<OutputEvent>,
+  cancel: Sender<()>,
+}
+
+impl Run {
+  /// Kills the command, it shows up as killed once it is gone.
+  pub fn cancel(&self) {
+    let _ = self.cancel.send(());
+  }
+}
+
+#[derive(Default)]
+pub struct OutputLog {
+  pub runs: Vec<Run>,
+  pub open: bool,
+  /// Whether the command bar is showing.
+  pub command_open: bool,
+  pub command_input: String,
+}
+
+/// Runs a command from the command bar in the current directory, with the
+/// selected files in `$FILES`, one per line.
+pub fn run_command(state: &mut Themis, ctx: &egui::Context, script: &str) -> Result<(), Error> {
+  let args = shell(OsStr::new(script));
+  let mut command = Command::new(&args[0]);
+  command.args(&args[1..]);
+  let mut files = OsString::new();
+  for (index, path) in state.selection.paths.iter().enumerate() {
+    if index > 0 {
+      files.push("\n");
+    }
+    files.push(path);
+  }
+  command.env("FILES", files);
+  let dir = state.current_path.clone();
+  spawn(state, ctx, script.to_owned(), command, true, true, &dir)
+}
+
+/// The platform shell running `script`, as program and arguments.
+#[cfg(unix)]
+pub fn shell(script: &OsStr) -> Vec<OsString> {
+  vec!["sh".into(), "-c".into(), script.to_os_string()]
+}
+
+#[cfg(not(unix))]
+pub fn shell(script: &OsStr) -> Vec<OsString> {
+  vec!["cmd".into(), "/C".into(), script.to_os_string()]
+}
+
+/// Quotes `text` as a single shell word, keeping its bytes as they are.
+#[cfg(unix)]
+pub fn quote(text: &OsStr) -> OsString {
+  use std::os::unix::ffi::{OsStrExt, OsStringExt};
+  let bytes = text.as_bytes();
+  let plain = |byte: &u8| byte.is_ascii_alphanumeric() || b"-_./=+,:@%".contains(byte);
+  if !bytes.is_empty() && bytes.iter().all(plain) {
+    return text.to_os_string();
+  }
+  let mut quoted = vec![b'\''];
+  for &byte in bytes {
+    if byte == b'\'' {
+      quoted.extend_from_slice(b"'\\''");
+    } else {
+      quoted.push(byte);
+    }
+  }
+  quoted.push(b'\'');
+  OsString::from_vec(quoted)
+}
+
+#[cfg(not(unix))]
+pub fn quote(text: &OsStr) -> OsString {
+  OsString::from(shell_words::quote(&text.to_string_lossy()).into_owned())
+}
+
+/// Starts `command`, streaming its output into the panel when `show` is set
+/// and leaving it on the inherited stdout otherwise.
+pub fn spawn(
+  state: &mut Themis,
+  ctx: &egui::Context,
+  title: String,
+  mut command: Command,
+  show: bool,
+  refresh: bool,
+  dir: &Path,
+) -> Result<(), Error> {
+  command.current_dir(dir).stdin(Stdio::null());
+  if show {
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+  }
+  own_process_group(&mut command);
+  let mut child = command.spawn().map_err(|err| Error::io("run a command in", dir, err))?;
+  let (sender, receiver) = unbounded();
+  let (cancel, cancelled) = unbounded::<()>();
+  let readers: Vec<_> = [
+    child.stdout.take().map(|out| Box::new(out) as Box<dyn Read + Send>),
+    child.stderr.take().map(|err| Box::new(err) as Box<dyn Read + Send>),
+  ]
+  .into_iter()
+  .flatten()
+  .map(|stream| {
+    let sender = sender.clone();
+    let ctx = ctx.clone();
+    thread::spawn(move || read_lines(stream, &sender, &ctx))
+  })
+  .collect();
+  let ctx = ctx.clone();
+  thread::spawn(move || {
+    let status = loop {
+      match child.try_wait() {
+        Ok(Some(status)) => break status.code(),
+        Ok(None) => {}
+        Err(_) => break None,
+      }
+      match cancelled.recv_timeout(Duration::from_millis(50)) {
+        Ok(()) => kill(&mut child),
+        // * Nobody can cancel any more, keep waiting at the same pace
+        Err(RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(50)),
+        Err(RecvTimeoutError::Timeout) => {}
+      }
+    };
+    for reader in readers {
+      let _ = reader.join();
+    }
+    let _ = sender.send(OutputEvent::Exit(status));
+    ctx.request_repaint();
+  });
+  if show {
+    state.output.open = true;
+  }
+  state.output.runs.push(Run {
+    title,
+    text: String::new(),
+    exit: None,
+    show,
+    refresh,
+    receiver,
+    cancel,
+  });
+  Ok(())
+}
+
+/// Puts the command in a process group of its own, so cancelling it also
+/// stops whatever it started.
+#[cfg(unix)]
+fn own_process_group(command: &mut Command) {
+  use std::os::unix::process::CommandExt;
+  command.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn own_process_group(_command: &mut Command) {}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+  use nix::sys::signal::{killpg, Signal};
+  use nix::unistd::Pid;
+  if killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL).is_err() {
+    let _ = child.kill();
+  }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+  let _ = child.kill();
+}
+
+fn read_lines(stream: Box<dyn Read + Send>, sender: &Sender<OutputEvent>, ctx: &egui::Context) {
+  let mut reader = BufReader::new(stream);
+  let mut line = Vec::new();
+  while let Ok(read) = reader.read_until(b'\n', &mut line) {
+    if read == 0 {
+      break;
+    }
+    let _ = sender.send(OutputEvent::Line(String::from_utf8_lossy(&line).into_owned()));
+    line.clear();
+    ctx.request_repaint();
+  }
+}
+
+/// Collects new output, and refreshes the listing when a command that
+/// asked for it has finished.
+pub fn poll(state: &mut Themis) {
+  let mut refresh = false;
+  for run in &mut state.output.runs {
+    for event in run.receiver.try_iter() {
+      match event {
+        OutputEvent::Line(line) => run.text.push_str(&line),
+        OutputEvent::Exit(code) => {
+          run.exit = Some(code);
+          refresh |= run.refresh;
+        }
+      }
+    }
+  }
+  // * Nothing left to show for finished commands that weren't captured
+  state
+    .output
+    .runs
+    .retain(|run| run.show || run.exit.is_none());
+  if refresh {
+    if let Err(err) = update_current_dir(state) {
+      state.errors.report(err);
+    }
+  }
+}
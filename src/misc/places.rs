@@ -0,0 +1,248 @@
+use crossbeam_channel::{bounded, Receiver};
+use eframe::egui;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often the mount table and free space are checked again.
+const DEVICE_POLL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Place {
+  pub name: String,
+  pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Device {
+  pub label: String,
+  pub mount_point: PathBuf,
+  pub source: String,
+  pub fs_type: String,
+  pub total: u64,
+  pub free: u64,
+}
+
+/// Home followed by the XDG user directories that are set up.
+pub fn user_places() -> Vec<Place> {
+  let home = match env::var_os("HOME") {
+    Some(home) => PathBuf::from(home),
+    None => return Vec::new(),
+  };
+  let mut places = vec![Place {
+    name: "Home".to_owned(),
+    path: home.clone(),
+  }];
+  let config = match env::var_os("XDG_CONFIG_HOME") {
+    Some(config) if !config.is_empty() => PathBuf::from(config),
+    _ => home.join(".config"),
+  };
+  let contents = fs::read_to_string(config.join("user-dirs.dirs")).unwrap_or_default();
+  for line in contents.lines().map(str::trim) {
+    let (key, value) = match line.split_once('=') {
+      Some(pair) if !line.starts_with('#') => pair,
+      _ => continue,
+    };
+    let name = match key.trim() {
+      "XDG_DESKTOP_DIR" => "Desktop",
+      "XDG_DOCUMENTS_DIR" => "Documents",
+      "XDG_DOWNLOAD_DIR" => "Downloads",
+      "XDG_MUSIC_DIR" => "Music",
+      "XDG_PICTURES_DIR" => "Pictures",
+      "XDG_VIDEOS_DIR" => "Videos",
+      "XDG_TEMPLATES_DIR" => "Templates",
+      "XDG_PUBLICSHARE_DIR" => "Public",
+      _ => continue,
+    };
+    let value = value.trim().trim_matches('"');
+    let path = match value.strip_prefix("$HOME") {
+      Some(rest) => home.join(rest.trim_start_matches('/')),
+      None => PathBuf::from(value),
+    };
+    // * A user directory pointing at home itself means it is turned off
+    if path != home && path.is_dir() {
+      places.push(Place {
+        name: name.to_owned(),
+        path,
+      });
+    }
+  }
+  places
+}
+
+/// Re-reads the devices in the background, sending the list whenever it
+/// changes. The thread stops once the receiver is dropped.
+pub fn watch_devices(ctx: egui::Context) -> Receiver<Vec<Device>> {
+  let (sender, receiver) = bounded(1);
+  thread::spawn(move || {
+    let mut last = None;
+    loop {
+      let devices = devices();
+      if last.as_ref() != Some(&devices) {
+        if sender.send(devices.clone()).is_err() {
+          return;
+        }
+        ctx.request_repaint();
+        last = Some(devices);
+      }
+      thread::sleep(DEVICE_POLL);
+    }
+  });
+  receiver
+}
+
+/// Real filesystems from the mount table, skipping pseudo filesystems and
+/// repeated bind mounts of the same device.
+#[cfg(target_os = "linux")]
+pub fn devices() -> Vec<Device> {
+  let contents = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+  let labels = labels();
+  mounts(&contents)
+    .into_iter()
+    .map(|(mount_point, fs_type, source)| {
+      let label = labels
+        .iter()
+        .find(|(_, device)| Path::new(&source).canonicalize().ok().as_ref() == Some(device))
+        .map(|(label, _)| label.clone())
+        .unwrap_or_else(|| match mount_point.file_name() {
+          Some(name) => name.to_string_lossy().into_owned(),
+          None => "File System".to_owned(),
+        });
+      let (total, free) = space(&mount_point);
+      Device {
+        label,
+        mount_point,
+        source,
+        fs_type,
+        total,
+        free,
+      }
+    })
+    .collect()
+}
+
+/// Mount point, type and source of the mounts worth showing. The same
+/// directory of a device mounted twice is a bind mount and only shown at
+/// its shortest mount point, other directories are subvolumes and kept.
+#[cfg(target_os = "linux")]
+fn mounts(contents: &str) -> Vec<(PathBuf, String, String)> {
+  const NETWORK: [&str; 6] = ["nfs", "nfs4", "cifs", "smb3", "fuse.sshfs", "9p"];
+  let mut mounts: Vec<((&str, &str), PathBuf, String, String)> = Vec::new();
+  for line in contents.lines() {
+    // * `id parent major:minor root mount_point options [optional...] - type source super_options`
+    let (left, right) = match line.split_once(" - ") {
+      Some(split) => split,
+      None => continue,
+    };
+    let left: Vec<&str> = left.split(' ').collect();
+    let right: Vec<&str> = right.split(' ').collect();
+    let fields = (left.get(2), left.get(3), left.get(4), right.first(), right.get(1));
+    let (device, root, mount_point, fs_type, source) = match fields {
+      (Some(device), Some(root), Some(mount_point), Some(fs_type), Some(source)) => {
+        (*device, *root, PathBuf::from(unescape(mount_point)), *fs_type, unescape(source))
+      }
+      _ => continue,
+    };
+    let is_block = source.starts_with("/dev/") && fs_type != "squashfs";
+    if !(is_block || NETWORK.contains(&fs_type)) {
+      continue;
+    }
+    let depth = |path: &Path| path.components().count();
+    match mounts.iter_mut().find(|(key, ..)| *key == (device, root)) {
+      Some(seen) if depth(&mount_point) < depth(&seen.1) => seen.1 = mount_point,
+      Some(_) => {}
+      None => mounts.push(((device, root), mount_point, fs_type.to_owned(), source)),
+    }
+  }
+  mounts
+    .into_iter()
+    .map(|(_, mount_point, fs_type, source)| (mount_point, fs_type, source))
+    .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn devices() -> Vec<Device> {
+  Vec::new()
+}
+
+/// Filesystem labels with the device they belong to.
+#[cfg(target_os = "linux")]
+fn labels() -> Vec<(String, PathBuf)> {
+  let dir = match fs::read_dir("/dev/disk/by-label") {
+    Ok(dir) => dir,
+    Err(_) => return Vec::new(),
+  };
+  dir
+    .flatten()
+    .filter_map(|entry| {
+      let device = entry.path().canonicalize().ok()?;
+      Some((unescape(&entry.file_name().to_string_lossy()), device))
+    })
+    .collect()
+}
+
+// * The statvfs field types differ between targets
+#[cfg(target_os = "linux")]
+#[allow(clippy::unnecessary_cast)]
+fn space(mount_point: &Path) -> (u64, u64) {
+  match nix::sys::statvfs::statvfs(mount_point) {
+    Ok(stats) => {
+      let fragment = stats.fragment_size() as u64;
+      (
+        stats.blocks() as u64 * fragment,
+        stats.blocks_available() as u64 * fragment,
+      )
+    }
+    Err(_) => (0, 0),
+  }
+}
+
+/// Undoes the `\NNN` octal (mountinfo) and `\xNN` hex (udev) escapes.
+#[cfg(target_os = "linux")]
+fn unescape(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    if bytes[index] == b'\\' {
+      let rest = &text[index + 1..];
+      let escaped = if let Some(hex) = rest.strip_prefix('x').and_then(|hex| hex.get(..2)) {
+        u8::from_str_radix(hex, 16).ok().map(|byte| (byte, 4))
+      } else {
+        rest.get(..3).and_then(|octal| u8::from_str_radix(octal, 8).ok()).map(|byte| (byte, 4))
+      };
+      if let Some((byte, length)) = escaped {
+        decoded.push(byte);
+        index += length;
+        continue;
+      }
+    }
+    decoded.push(bytes[index]);
+    index += 1;
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn subvolumes_stay_and_bind_mounts_go() {
+    let mountinfo = "\
+22 1 0:21 /@ / rw,relatime - btrfs /dev/sda2 rw
+23 22 0:21 /@home /home rw,relatime - btrfs /dev/sda2 rw
+24 22 0:22 / /proc rw - proc proc rw
+25 23 0:21 /@home /srv/my\\040home rw,relatime - btrfs /dev/sda2 rw
+26 22 8:1 / /boot/efi rw - vfat /dev/sda1 rw
+27 22 7:0 / /snap/core/1 ro - squashfs /dev/loop0 ro
+";
+    let mounts: Vec<PathBuf> = mounts(mountinfo).into_iter().map(|(mount_point, ..)| mount_point).collect();
+    assert_eq!(
+      mounts,
+      vec![PathBuf::from("/"), PathBuf::from("/home"), PathBuf::from("/boot/efi")]
+    );
+  }
+}
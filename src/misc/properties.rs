@@ -0,0 +1,373 @@
+use filetime::FileTime;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::app::{Error, Themis};
+use crate::misc::mime::FileType;
+use crate::misc::search::update_current_dir;
+
+/// Everything the Properties window shows about one path.
+pub struct Info {
+  pub size: u64,
+  /// Only known on unix, where the block count is available.
+  pub allocated: Option<u64>,
+  pub is_dir: bool,
+  pub created: Option<SystemTime>,
+  pub modified: Option<SystemTime>,
+  pub accessed: Option<SystemTime>,
+  pub changed: Option<SystemTime>,
+  pub inode: Option<u64>,
+  pub links: Option<u64>,
+  pub owner: Option<(u32, String)>,
+  pub group: Option<(u32, String)>,
+  pub mode: Option<u32>,
+  pub symlink_target: Option<PathBuf>,
+  pub file_type: FileType,
+  pub xattrs: Vec<(String, String)>,
+}
+
+/// The open Properties window, with the edits that have not been applied.
+pub struct Properties {
+  pub path: PathBuf,
+  pub info: Info,
+  pub mode: u32,
+  pub recursive: bool,
+  pub owner_input: String,
+  pub group_input: String,
+  pub modified_input: String,
+  pub accessed_input: String,
+}
+
+pub fn open(state: &mut Themis, path: PathBuf) {
+  match read(state, &path) {
+    Ok(info) => {
+      state.properties = Some(Properties {
+        mode: info.mode.unwrap_or_default(),
+        recursive: false,
+        owner_input: info.owner.as_ref().map(|(_, name)| name.clone()).unwrap_or_default(),
+        group_input: info.group.as_ref().map(|(_, name)| name.clone()).unwrap_or_default(),
+        modified_input: info.modified.map(format_time).unwrap_or_default(),
+        accessed_input: info.accessed.map(format_time).unwrap_or_default(),
+        path,
+        info,
+      })
+    }
+    Err(err) => state.errors.report(err),
+  }
+}
+
+/// Re-reads the metadata after an edit, keeping the window open.
+pub fn reload(state: &mut Themis) {
+  if let Some(path) = state.properties.as_ref().map(|properties| properties.path.clone()) {
+    let recursive = matches!(&state.properties, Some(properties) if properties.recursive);
+    open(state, path);
+    if let Some(properties) = &mut state.properties {
+      properties.recursive = recursive;
+    }
+  }
+  if let Err(err) = update_current_dir(state) {
+    state.errors.report(err);
+  }
+}
+
+fn read(state: &Themis, path: &Path) -> Result<Info, Error> {
+  let link = fs::symlink_metadata(path).map_err(|err| Error::io("inspect", path, err))?;
+  let symlink_target = if link.file_type().is_symlink() {
+    fs::read_link(path).ok()
+  } else {
+    None
+  };
+  // * Describe what the link points at, falling back to the link itself
+  let metadata = fs::metadata(path).unwrap_or(link);
+  let stats = state.dir_sizes.dirs.get(path);
+  let mut info = Info {
+    size: stats.map_or(metadata.len(), |stats| stats.size),
+    allocated: stats.map(|stats| stats.allocated),
+    is_dir: metadata.is_dir(),
+    created: metadata.created().ok(),
+    modified: metadata.modified().ok(),
+    accessed: metadata.accessed().ok(),
+    changed: None,
+    inode: None,
+    links: None,
+    owner: None,
+    group: None,
+    mode: None,
+    symlink_target,
+    file_type: state.mime.detect(path, metadata.is_dir(), false),
+    xattrs: read_xattrs(path),
+  };
+  read_unix(&mut info, &metadata);
+  Ok(info)
+}
+
+#[cfg(unix)]
+fn read_unix(info: &mut Info, metadata: &Metadata) {
+  use nix::unistd::{Gid, Group, Uid, User};
+  use std::os::unix::fs::MetadataExt;
+
+  if info.allocated.is_none() {
+    info.allocated = Some(metadata.blocks() * 512);
+  }
+  info.changed = UNIX_EPOCH.checked_add(Duration::from_secs(metadata.ctime().max(0) as u64));
+  info.inode = Some(metadata.ino());
+  info.links = Some(metadata.nlink());
+  info.mode = Some(metadata.mode() & 0o7777);
+  let uid = metadata.uid();
+  let owner = match User::from_uid(Uid::from_raw(uid)) {
+    Ok(Some(user)) => user.name,
+    _ => uid.to_string(),
+  };
+  info.owner = Some((uid, owner));
+  let gid = metadata.gid();
+  let group = match Group::from_gid(Gid::from_raw(gid)) {
+    Ok(Some(group)) => group.name,
+    _ => gid.to_string(),
+  };
+  info.group = Some((gid, group));
+}
+
+#[cfg(not(unix))]
+fn read_unix(_info: &mut Info, _metadata: &Metadata) {}
+
+fn read_xattrs(path: &Path) -> Vec<(String, String)> {
+  let names = match xattr::list(path) {
+    Ok(names) => names,
+    Err(_) => return Vec::new(),
+  };
+  names
+    .map(|name| {
+      let value = xattr::get(path, &name).ok().flatten().unwrap_or_default();
+      (
+        name.to_string_lossy().into_owned(),
+        String::from_utf8_lossy(&value).into_owned(),
+      )
+    })
+    .collect()
+}
+
+/// Applies `mode` to the path, and to everything below it when
+/// `recursive` is set. Files below only keep execute bits they already had.
+/// Directories are changed last, so a mode without read or execute bits
+/// doesn't lock us out of their contents halfway through.
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32, recursive: bool) -> Result<(), Error> {
+  use std::os::unix::fs::PermissionsExt;
+
+  if recursive && path.is_dir() {
+    let dir = match fs::read_dir(path) {
+      Ok(dir) => dir,
+      // * A directory we can't list yet is opened up for us first
+      Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+        let current = fs::metadata(path)
+          .map_err(|err| Error::io("read permissions of", path, err))?
+          .permissions()
+          .mode();
+        fs::set_permissions(path, fs::Permissions::from_mode(current | 0o700))
+          .map_err(|err| Error::io("change permissions of", path, err))?;
+        fs::read_dir(path).map_err(|err| Error::io("read directory", path, err))?
+      }
+      Err(err) => return Err(Error::io("read directory", path, err)),
+    };
+    for entry in dir.flatten() {
+      let metadata = match entry.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => continue,
+      };
+      if metadata.file_type().is_symlink() {
+        continue;
+      }
+      if metadata.is_dir() {
+        set_mode(&entry.path(), mode, true)?;
+      } else {
+        let executable = metadata.permissions().mode() & 0o111;
+        set_mode(&entry.path(), mode & !0o111 | mode & executable, false)?;
+      }
+    }
+  }
+  fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    .map_err(|err| Error::io("change permissions of", path, err))
+}
+
+#[cfg(not(unix))]
+pub fn set_mode(_path: &Path, _mode: u32, _recursive: bool) -> Result<(), Error> {
+  Ok(())
+}
+
+/// Changes owner and group, each given as a name or a numeric id.
+#[cfg(unix)]
+pub fn set_owner(path: &Path, owner: &str, group: &str) -> Result<(), Error> {
+  use nix::unistd::{chown, Gid, Group, Uid, User};
+
+  let uid = match owner.trim().parse::<u32>() {
+    Ok(uid) => Uid::from_raw(uid),
+    Err(_) => match User::from_name(owner.trim()) {
+      Ok(Some(user)) => user.uid,
+      _ => return Err(Error::Invalid(format!("No user called {}", owner.trim()))),
+    },
+  };
+  let gid = match group.trim().parse::<u32>() {
+    Ok(gid) => Gid::from_raw(gid),
+    Err(_) => match Group::from_name(group.trim()) {
+      Ok(Some(group)) => group.gid,
+      _ => return Err(Error::Invalid(format!("No group called {}", group.trim()))),
+    },
+  };
+  chown(path, Some(uid), Some(gid))
+    .map_err(|err| Error::io("change the owner of", path, err.into()))
+}
+
+#[cfg(not(unix))]
+pub fn set_owner(_path: &Path, _owner: &str, _group: &str) -> Result<(), Error> {
+  Err(Error::Invalid("Owners can only be changed on unix".to_owned()))
+}
+
+pub fn set_times(path: &Path, accessed: &str, modified: &str) -> Result<(), Error> {
+  let parse = |input: &str| {
+    parse_time(input)
+      .map(FileTime::from_system_time)
+      .ok_or_else(|| Error::Invalid(format!("{} is not a valid time", input.trim())))
+  };
+  filetime::set_file_times(path, parse(accessed)?, parse(modified)?)
+    .map_err(|err| Error::io("change the timestamps of", path, err))
+}
+
+/// Formats as "YYYY-MM-DD HH:MM:SS" in UTC.
+pub fn format_time(time: SystemTime) -> String {
+  let seconds = match time.duration_since(UNIX_EPOCH) {
+    Ok(duration) => duration.as_secs() as i64,
+    Err(err) => -(err.duration().as_secs() as i64),
+  };
+  let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+  let time = seconds.rem_euclid(86400);
+  format!(
+    "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+    year,
+    month,
+    day,
+    time / 3600,
+    time / 60 % 60,
+    time % 60
+  )
+}
+
+/// The inverse of `format_time`.
+pub fn parse_time(input: &str) -> Option<SystemTime> {
+  let (date, time) = input.trim().split_once(' ')?;
+  let mut date = date.split('-').map(|part| part.parse::<i64>());
+  let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+  let mut time = time.split(':').map(|part| part.parse::<i64>());
+  let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+  if !(1..=12).contains(&month)
+    || !(1..=31).contains(&day)
+    || !(0..24).contains(&hour)
+    || !(0..60).contains(&minute)
+    || !(0..60).contains(&second)
+  {
+    return None;
+  }
+  // * Days past the end of the month, like 2001-02-29, would roll over
+  let days = days_from_civil(year, month, day);
+  if civil_from_days(days) != (year, month, day) {
+    return None;
+  }
+  let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+  if seconds >= 0 {
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+  } else {
+    UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+  }
+}
+
+/// Howard Hinnant's `civil_from_days`, days since 1970-01-01 to a date.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days.rem_euclid(146097);
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 {
+    shifted_month + 3
+  } else {
+    shifted_month - 9
+  };
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+/// The inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year.rem_euclid(400);
+  let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+  let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(seconds: i64) -> SystemTime {
+    if seconds >= 0 {
+      UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+      UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+  }
+
+  #[test]
+  fn known_dates() {
+    assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00:00");
+    assert_eq!(format_time(at(951_782_400)), "2000-02-29 00:00:00");
+    assert_eq!(format_time(at(-2_203_891_200)), "1900-03-01 00:00:00");
+    assert_eq!(format_time(at(-1)), "1969-12-31 23:59:59");
+    assert_eq!(parse_time("2000-02-29 12:30:45"), Some(at(951_827_445)));
+    assert_eq!(parse_time(" 1969-07-20 20:17:40 "), Some(at(-14_182_940)));
+  }
+
+  #[test]
+  fn days_round_trip() {
+    for days in [-719_468, -25_567, -1, 0, 1, 10_956, 11_016, 2_932_896] {
+      let (year, month, day) = civil_from_days(days);
+      assert_eq!(days_from_civil(year, month, day), days);
+    }
+    // * 1900 is not a leap year, 2000 is
+    assert_eq!(civil_from_days(days_from_civil(1900, 2, 28) + 1), (1900, 3, 1));
+    assert_eq!(civil_from_days(days_from_civil(2000, 2, 28) + 1), (2000, 2, 29));
+  }
+
+  #[test]
+  fn times_round_trip() {
+    for seconds in [0, 59, 86_399, 951_782_400, -1, -86_401, -2_203_891_200, 4_102_444_799] {
+      assert_eq!(parse_time(&format_time(at(seconds))), Some(at(seconds)));
+    }
+  }
+
+  #[test]
+  fn malformed_input_is_rejected() {
+    for input in [
+      "",
+      "2000-02-29",
+      "2000-02-29T00:00:00",
+      "2000-13-01 00:00:00",
+      "2000-00-10 00:00:00",
+      "2000-01-32 00:00:00",
+      "2001-02-29 00:00:00",
+      "1900-02-29 00:00:00",
+      "2000-04-31 00:00:00",
+      "2000-01-01 24:00:00",
+      "2000-01-01 00:60:00",
+      "2000-01-01 00:00",
+      "twenty-01-01 00:00:00",
+    ] {
+      assert_eq!(parse_time(input), None, "{:?}", input);
+    }
+  }
+}
@@ -1,147 +1,383 @@
-use crate::app::{DirEntry, DirWatcherEvent, Themis};
-use glob::Pattern;
-use regex::Regex;
+use crate::app::{DirEntry, DirWatcherEvent, Error, Themis};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
 use std::env::set_current_dir;
+use std::ffi::OsString;
 use std::fs::read_dir;
-use std::path::PathBuf;
-
-use crate::ui::settings::{SearchMode, MatchMode};
-
-pub fn update_search(state: &mut Themis) {
-  if state.search == "" {}
-  let dir_path = std::path::Path::new(&state.current_path);
-  if let Ok(dir) = read_dir(dir_path) {
-    state.search_results = Vec::new();
-    let mut reg: String = "".to_owned();
-    for part in state.current_path.into_iter() {
-      if part != "\\" {
-        reg.push_str(part.to_str().unwrap());
-        reg.push_str("\\\\");
+use std::path::{Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+
+use crate::misc::excludes::Excludes;
+use crate::misc::name::escape;
+use crate::misc::views::{self, View};
+use crate::ui::settings::{MatchMode, SearchMode, SearchSettings, SortMode};
+
+pub fn update_search(state: &mut Themis) -> Result<(), Error> {
+  state.tags.stale = true;
+  if let Some(tag) = state.search.strip_prefix("tag:") {
+    let tag = tag.trim().to_owned();
+    return search_tag(state, &tag);
+  }
+  let dir_path = state.current_path.clone();
+  let dir = read_dir(&dir_path).map_err(|err| Error::io("read directory", &dir_path, err))?;
+  state.search_results = Vec::new();
+  let matcher = Matcher::new(&state.search, &dir_path, &state.settings.search);
+  let mut excludes = Excludes::new(&dir_path, &state.settings.search);
+
+  if state.settings.search.recursive && !state.filesystem.files.is_empty() {
+    for path in state.filesystem.files.keys() {
+      let is_dir = state.dir_sizes.dirs.contains_key(Path::new(path));
+      if matcher.matches(Path::new(path)) && !excludes.is_excluded(Path::new(path), is_dir) {
+        state.search_results.push(update(
+          state,
+          PathBuf::from(path.clone())
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string(),
+          PathBuf::from(path.clone()),
+        ));
       }
     }
-    reg.push_str(&state.search.clone());
-
-    if state.settings.search.match_mode == MatchMode::Strict {
-      reg.push('$');
+  } else {
+    for entry in dir {
+      let path = match entry {
+        Ok(entry) => entry.path(),
+        Err(err) => return Err(Error::io("read directory", &dir_path, err)),
+      };
+      let name = path.file_name().unwrap_or_default().to_os_string();
+      let excluded = excludes.is_active() && excludes.is_excluded(&path, path.is_dir());
+      if !excluded && (state.search.is_empty() || matcher.matches(&path)) {
+        state.search_results.push(update(state, name, path));
+      }
     }
-    else if state.settings.search.match_mode == MatchMode::Normal {
-      reg.push_str("[^\\\\]*$");
+  }
+  let search_settings = &state.settings.search;
+  state
+    .search_results
+    .retain(|entry| size_matches(search_settings, entry));
+  sort_entries(&mut state.search_results, &state.view);
+  Ok(())
+}
+
+/// Matches paths against a query the way the search bar does, also used by
+/// the `search` subcommand. Only what comes after the base directory is
+/// matched, and always in its escaped form so a path reads the same whether
+/// it came from the index or the disk.
+pub struct Matcher {
+  mode: SearchMode,
+  match_mode: MatchMode,
+  sensitive: bool,
+  /// The base directory with a trailing separator, lowercased when the
+  /// search ignores case.
+  base: String,
+  /// The raw query, lowercased when ignoring case.
+  query: String,
+  glob: Pattern,
+  glob_options: MatchOptions,
+  regex: Regex,
+  fuzzy: SkimMatcherV2,
+}
+
+impl Matcher {
+  pub fn new(query: &str, base: &Path, settings: &SearchSettings) -> Self {
+    let mut base_text = escape(base.as_os_str());
+    if !base_text.is_empty() && !base_text.ends_with(MAIN_SEPARATOR) {
+      base_text.push(MAIN_SEPARATOR);
     }
-    if !state.settings.search.sensitive {
-      reg = reg.to_lowercase();
-    };
+    let base = base_text;
 
-    let matcher = reg;
-    println!("{}", matcher);
-
-    let glob = Pattern::new(&matcher).unwrap_or(Pattern::new("!*").unwrap());
-    let re = Regex::new(&matcher).unwrap_or(Regex::new("$-").unwrap());
-
-    if state.settings.search.recursive && !state.filesystem.files.is_empty() {
-      for path in state.filesystem.files.keys() {
-        let search;
-        if !state.settings.search.sensitive {
-          search = path.clone().to_lowercase();
-        } else {
-          search = path.clone();
-        };
-        // TODO: only make mode checks at the start of the loop
-        if state.settings.search.search_mode == SearchMode::Glob && glob.matches(&search)
-          || state.settings.search.search_mode == SearchMode::Regex && re.is_match(&search)
-          || re.is_match(&search)
-        {
-          state.search_results.push(update(
-            state,
-            PathBuf::from(path.clone())
-              .file_name()
-              .unwrap()
-              .to_str()
-              .unwrap_or("")
-              .to_string(),
-            PathBuf::from(path.clone()),
-          ));
-        }
+    let not_separator = format!("[^{}]*", regex::escape(MAIN_SEPARATOR_STR));
+    // * Grouped so an alternation in the query can't escape the base
+    let mut reg = format!("^{}(?:{})", regex::escape(&base), query);
+    let mut glob = format!("{}{}", Pattern::escape(&base), query);
+    match settings.match_mode {
+      MatchMode::Strict => reg.push('$'),
+      MatchMode::Normal => {
+        reg.push_str(&not_separator);
+        reg.push('$');
+        glob.push('*');
       }
+      MatchMode::Loose => glob.push('*'),
+    }
+
+    let fuzzy = if settings.sensitive {
+      SkimMatcherV2::default().respect_case()
     } else {
-      for entry in dir {
-        let path = entry.unwrap().path();
-        let name = path
-          .clone()
-          .file_name()
-          .unwrap()
-          .to_str()
-          .unwrap()
-          .to_owned();
-
-        let search = if state.settings.search.sensitive {
-          path.clone().to_str().unwrap().to_string()
-        } else {
-          path.clone().to_str().unwrap().to_lowercase()
-        };
-
-        if state.search == ""
-          || state.settings.search.search_mode == SearchMode::Glob && glob.matches(&search)
-          || state.settings.search.search_mode == SearchMode::Regex && re.is_match(&search)
-          || state.settings.search.search_mode == SearchMode::Contains && search.contains(&matcher)
-        {
-          state.search_results.push(update(state, name, path));
-        }
+      SkimMatcherV2::default().ignore_case()
+    };
+    let lower = |text: &str| {
+      if settings.sensitive {
+        text.to_owned()
+      } else {
+        text.to_lowercase()
       }
+    };
+    Self {
+      mode: settings.search_mode.clone(),
+      match_mode: settings.match_mode.clone(),
+      sensitive: settings.sensitive,
+      glob: Pattern::new(&glob).unwrap_or_else(|_| Pattern::new("!*").unwrap()),
+      glob_options: MatchOptions {
+        case_sensitive: settings.sensitive,
+        // * Normal matching stays inside the last part of the path
+        require_literal_separator: settings.match_mode == MatchMode::Normal,
+        require_literal_leading_dot: false,
+      },
+      regex: RegexBuilder::new(&reg)
+        .case_insensitive(!settings.sensitive)
+        .build()
+        .unwrap_or_else(|_| Regex::new("$-").unwrap()),
+      base: lower(&base),
+      query: lower(query),
+      fuzzy,
     }
   }
-}
 
-pub fn update_current_dir(state: &mut Themis) {
-  if state.search == "" {
-    let dir_path = std::path::Path::new(&state.current_path);
-    if let Ok(dir) = read_dir(dir_path) {
-      set_current_dir(dir_path).unwrap();
-      state.navigation = dir_path.to_str().unwrap().to_owned();
-      state.current_path = dir_path.to_path_buf();
-      state.dir_entries = Vec::new();
-
-      for entry in dir {
-        let path = entry.unwrap().path();
-        let name = path
-          .clone()
-          .file_name()
-          .unwrap()
-          .to_str()
-          .unwrap()
-          .to_owned();
-        state.dir_entries.push(update(state, name, path));
+  pub fn matches(&self, path: &Path) -> bool {
+    let path = escape(path.as_os_str());
+    let lowered;
+    let search = match self.mode {
+      SearchMode::Glob => return self.glob.matches_with(&path, self.glob_options),
+      SearchMode::Regex => return self.regex.is_match(&path),
+      _ if self.sensitive => &path,
+      _ => {
+        lowered = path.to_lowercase();
+        &lowered
       }
+    };
+    let rest = match search.strip_prefix(&self.base) {
+      Some(rest) => rest,
+      None => return false,
+    };
+    if self.mode == SearchMode::Fuzzy {
+      return self.fuzzy.fuzzy_match(rest, &self.query).is_some();
+    }
+    match self.match_mode {
+      MatchMode::Strict => rest.ends_with(&self.query),
+      MatchMode::Normal => rest
+        .rfind(&self.query)
+        .is_some_and(|at| !rest[at + self.query.len()..].contains(MAIN_SEPARATOR)),
+      MatchMode::Loose => rest.contains(&self.query),
+    }
+  }
+}
+
+/// `tag:name` lists everything carrying the tag, across the whole disk when
+/// searching recursively and in the current directory otherwise.
+fn search_tag(state: &mut Themis, tag: &str) -> Result<(), Error> {
+  let sensitive = state.settings.search.sensitive;
+  let matches = |candidate: &str| {
+    if sensitive {
+      candidate == tag
+    } else {
+      candidate.eq_ignore_ascii_case(tag)
     }
+  };
+  let paths: Vec<PathBuf> = if state.settings.search.recursive {
+    state
+      .tags
+      .by_tag
+      .iter()
+      .filter(|(name, _)| matches(name))
+      .flat_map(|(_, paths)| paths.iter().cloned())
+      .filter(|path| path.exists())
+      .collect()
+  } else {
+    let dir_path = state.current_path.clone();
+    read_dir(&dir_path)
+      .map_err(|err| Error::io("read directory", &dir_path, err))?
+      .flatten()
+      .map(|entry| entry.path())
+      .collect()
+  };
+  let mut results: Vec<DirEntry> = paths
+    .into_iter()
+    .map(|path| {
+      let mut entry = read_entry(state, path);
+      entry.tags = state.tags.read(&entry.path);
+      entry
+    })
+    .filter(|entry| entry.tags.iter().any(|name| matches(name)))
+    .collect();
+  let search_settings = &state.settings.search;
+  results.retain(|entry| size_matches(search_settings, entry));
+  sort_entries(&mut results, &state.view);
+  state.search_results = results;
+  Ok(())
+}
+
+pub fn update_current_dir(state: &mut Themis) -> Result<(), Error> {
+  if state.search.is_empty() {
+    let dir_path = state.current_path.clone();
+    let dir = match read_dir(&dir_path) {
+      Ok(dir) => dir,
+      Err(err) => {
+        // * Stay where we were instead of showing a half-updated location
+        state.current_path = state.last_path.clone();
+        return Err(Error::io("read directory", &dir_path, err));
+      }
+    };
+    set_current_dir(&dir_path).map_err(|err| Error::io("enter directory", &dir_path, err))?;
+    state.navigation = dir_path.to_string_lossy().into_owned();
+    state.dir_entries = Vec::new();
     if state.last_path != state.current_path {
+      let from = state.last_path.clone();
+      if let Err(err) = views::switch(state, &from, &dir_path) {
+        state.errors.report(err);
+      }
+    }
+
+    let mut failed = None;
+    for entry in dir {
+      let path = match entry {
+        Ok(entry) => entry.path(),
+        Err(err) => {
+          failed = Some(Error::io("read directory", &dir_path, err));
+          continue;
+        }
+      };
+      let name = path.file_name().unwrap_or_default().to_os_string();
+      state.dir_entries.push(update(state, name, path));
+    }
+    sort_entries(&mut state.dir_entries, &state.view);
+    state.tags.stale = true;
+    if state.last_path == state.current_path {
+      let listed: Vec<PathBuf> = state.dir_entries.iter().map(|entry| entry.path.clone()).collect();
+      state.selection.retain_listed(&listed);
+    } else {
+      state.selection.clear();
+      state.jump.record(&state.current_path);
       state
         .dir_watcher
         .watcher_updater
         .send((DirWatcherEvent::Remove, state.last_path.clone()))
-        .unwrap();
+        .map_err(|_| Error::Disconnected("watcher"))?;
       state
         .dir_watcher
         .watcher_updater
         .send((DirWatcherEvent::Add, state.current_path.clone()))
-        .unwrap();
+        .map_err(|_| Error::Disconnected("watcher"))?;
       state.last_path = state.current_path.clone();
     }
+    if let Some(err) = failed {
+      return Err(err);
+    }
+  }
+  Ok(())
+}
+
+pub fn sort_entries(entries: &mut [DirEntry], view: &View) {
+  match view.sort_mode {
+    SortMode::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+    SortMode::Size => entries.sort_by_key(|entry| entry.size),
+    SortMode::Files => {
+      entries.sort_by_key(|entry| entry.stats.map_or(0, |stats| stats.files))
+    }
+  }
+  if view.sort_descending {
+    entries.reverse();
+  }
+}
+
+fn size_matches(settings: &SearchSettings, entry: &DirEntry) -> bool {
+  !matches!(settings.min_size, Some(min) if entry.size < min)
+    && !matches!(settings.max_size, Some(max) if entry.size > max)
+}
+
+/// Builds the entry for a single path, taking file sizes straight from disk
+/// since the index may not have caught up with the change yet.
+pub fn read_entry(state: &Themis, path: PathBuf) -> DirEntry {
+  let name = path.file_name().unwrap_or_default().to_os_string();
+  let mut entry = update(state, name, path);
+  if !entry.is_dir {
+    if let Ok(metadata) = std::fs::metadata(&entry.path) {
+      entry.size = metadata.len();
+    }
   }
+  entry
 }
 
-fn update(state: &Themis, name: String, path: PathBuf) -> DirEntry {
-  let size = match state
-    .filesystem
-    .files
-    .get(&path.clone().into_os_string().into_string().unwrap())
-  {
-    Some(size) => size.real_size,
-    None => 0,
+fn update(state: &Themis, name: OsString, path: PathBuf) -> DirEntry {
+  // * The index is keyed by UTF-8 paths, anything else is read from disk
+  let size = match path.to_str() {
+    Some(key) => state.filesystem.files.get(key).map_or(0, |file| file.real_size),
+    None => std::fs::symlink_metadata(&path).map_or(0, |metadata| metadata.len()),
   };
+  let is_dir = path.is_dir();
+  let stats = if is_dir {
+    state.dir_sizes.dirs.get(&path).copied()
+  } else {
+    None
+  };
+  let is_empty = is_dir && matches!(path.read_dir().map(|mut dir| dir.next().is_none()), Ok(true));
   DirEntry {
     name,
-    path: path.clone(),
-    size,
-    is_dir: path.clone().is_dir(),
-    is_empty: path.is_dir() && path.read_dir().unwrap().count() == 0,
+    is_empty,
+    file_type: state.mime.guess(&path, is_dir, is_empty),
+    size: stats.map_or(size, |stats| stats.size),
+    tags: state.tags.known(&path),
+    git: state.git.repo.as_ref().and_then(|repo| repo.status_of(&path, is_dir)),
+    path,
+    is_dir,
+    stats,
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+
+  fn matcher(query: &str, mode: SearchMode, match_mode: MatchMode) -> Matcher {
+    let settings = SearchSettings {
+      search_mode: mode,
+      match_mode,
+      ..SearchSettings::default()
+    };
+    Matcher::new(query, Path::new("/home/me"), &settings)
+  }
+
+  #[test]
+  fn contains_uses_the_raw_query() {
+    let matcher = matcher("Report", SearchMode::Contains, MatchMode::Normal);
+    assert!(matcher.matches(Path::new("/home/me/q3 report.pdf")));
+    assert!(matcher.matches(Path::new("/home/me/old/report.pdf")));
+    assert!(!matcher.matches(Path::new("/home/me/reports/q3.pdf")));
+    assert!(!matcher.matches(Path::new("/home/other/report.pdf")));
+  }
+
+  #[test]
+  fn fuzzy_matches_below_the_base() {
+    let matcher = matcher("qrpt", SearchMode::Fuzzy, MatchMode::Normal);
+    assert!(matcher.matches(Path::new("/home/me/q3 report.pdf")));
+    assert!(!matcher.matches(Path::new("/srv/q3 report.pdf")));
+  }
+
+  #[test]
+  fn regex_and_glob_are_anchored_at_the_base() {
+    let regex = matcher(r"\d+\.txt", SearchMode::Regex, MatchMode::Strict);
+    assert!(regex.matches(Path::new("/home/me/12.txt")));
+    assert!(!regex.matches(Path::new("/home/me/12.txt.bak")));
+    let glob = matcher("*.rs", SearchMode::Glob, MatchMode::Normal);
+    assert!(glob.matches(Path::new("/home/me/MAIN.rs")));
+    assert!(!glob.matches(Path::new("/home/me/src/main.rs")));
+    let loose = matcher("*.rs", SearchMode::Glob, MatchMode::Loose);
+    assert!(loose.matches(Path::new("/home/me/src/main.rs")));
+    let regex = matcher(r"a\.txt|b\.txt", SearchMode::Regex, MatchMode::Strict);
+    assert!(regex.matches(Path::new("/home/me/b.txt")));
+    assert!(!regex.matches(Path::new("/srv/home/me/a.txt")));
+    assert!(!regex.matches(Path::new("/srv/b.txt")));
+  }
+
+  #[test]
+  fn odd_names_match_by_their_escaped_form() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    // * A backslash is listed as `\\`, so that is what the query has to say
+    let contains = matcher(r"a\\b", SearchMode::Contains, MatchMode::Normal);
+    assert!(contains.matches(Path::new(r"/home/me/a\b.txt")));
+    assert!(!contains.matches(Path::new(r"/home/me/a\\b.txt")));
+    let regex = matcher(r"caf\\xE9\.txt", SearchMode::Regex, MatchMode::Strict);
+    assert!(regex.matches(Path::new(OsStr::from_bytes(b"/home/me/caf\xE9.txt"))));
   }
 }
@@ -0,0 +1,113 @@
+//! The entries picked in the listing, for commands that take several files.
+
+use std::path::{Path, PathBuf};
+
+#[derive(Default)]
+pub struct Selection {
+  /// In the order they were picked.
+  pub paths: Vec<PathBuf>,
+  /// Where a Shift+click range starts, the last plain or Ctrl+click.
+  anchor: Option<PathBuf>,
+}
+
+impl Selection {
+  pub fn contains(&self, path: &Path) -> bool {
+    self.paths.iter().any(|selected| selected == path)
+  }
+
+  /// A plain click, selecting only `path`.
+  pub fn select(&mut self, path: &Path) {
+    self.paths = vec![path.to_path_buf()];
+    self.anchor = Some(path.to_path_buf());
+  }
+
+  /// Ctrl+click, adding or taking away `path`.
+  pub fn toggle(&mut self, path: &Path) {
+    match self.paths.iter().position(|selected| selected == path) {
+      Some(index) => {
+        self.paths.remove(index);
+      }
+      None => self.paths.push(path.to_path_buf()),
+    }
+    self.anchor = Some(path.to_path_buf());
+  }
+
+  /// Shift+click, selecting everything `listed` between the anchor and
+  /// `path`. Without an anchor in the listing it's a plain click.
+  pub fn extend_to(&mut self, path: &Path, listed: &[PathBuf]) {
+    let anchor = self
+      .anchor
+      .as_ref()
+      .and_then(|anchor| listed.iter().position(|listed| listed == anchor));
+    let (anchor, end) = match (anchor, listed.iter().position(|listed| listed == path)) {
+      (Some(anchor), Some(end)) => (anchor, end),
+      _ => return self.select(path),
+    };
+    let (first, last) = (anchor.min(end), anchor.max(end));
+    self.paths = listed[first..=last].to_vec();
+  }
+
+  pub fn clear(&mut self) {
+    self.paths.clear();
+    self.anchor = None;
+  }
+
+  /// Forgets paths that are no longer listed.
+  pub fn retain_listed(&mut self, listed: &[PathBuf]) {
+    self.paths.retain(|path| listed.contains(path));
+  }
+
+  /// What a command on `path` applies to: the whole selection when `path`
+  /// is part of it, `path` alone otherwise.
+  pub fn targets(&self, path: &Path) -> Vec<PathBuf> {
+    if self.contains(path) {
+      self.paths.clone()
+    } else {
+      vec![path.to_path_buf()]
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn listed() -> Vec<PathBuf> {
+    ["a", "b", "c", "d"].iter().map(PathBuf::from).collect()
+  }
+
+  #[test]
+  fn ranges_go_either_way_from_the_anchor() {
+    let listed = listed();
+    let mut selection = Selection::default();
+    selection.select(Path::new("c"));
+    selection.extend_to(Path::new("a"), &listed);
+    assert_eq!(selection.paths, &listed[..3]);
+    selection.extend_to(Path::new("d"), &listed);
+    assert_eq!(selection.paths, &listed[2..]);
+  }
+
+  #[test]
+  fn toggling_moves_the_anchor() {
+    let listed = listed();
+    let mut selection = Selection::default();
+    selection.select(Path::new("a"));
+    selection.toggle(Path::new("c"));
+    assert_eq!(selection.paths, vec![PathBuf::from("a"), PathBuf::from("c")]);
+    selection.extend_to(Path::new("d"), &listed);
+    assert_eq!(selection.paths, &listed[2..]);
+    selection.toggle(Path::new("a"));
+    assert_eq!(selection.paths.len(), 3);
+    selection.toggle(Path::new("a"));
+    assert_eq!(selection.paths, &listed[2..]);
+  }
+
+  #[test]
+  fn targets_fall_back_to_the_path() {
+    let mut selection = Selection::default();
+    selection.select(Path::new("a"));
+    selection.toggle(Path::new("b"));
+    assert_eq!(selection.targets(Path::new("b")).len(), 2);
+    assert_eq!(selection.targets(Path::new("c")), vec![PathBuf::from("c")]);
+  }
+}
@@ -0,0 +1,376 @@
+use crossbeam_channel::{bounded, Receiver};
+use eframe::egui;
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::app::{Error, Themis};
+use crate::misc::name::{path_bytes, path_from_bytes};
+
+/// The attribute other desktop tools read tags from, a comma separated list.
+const XATTR: &str = "user.xdg.tags";
+
+/// How long a write waits for one on another connection to finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tags that double as color labels, with the color their chips get.
+pub const COLOR_LABELS: [(&str, [u8; 3]); 7] = [
+  ("Red", [220, 80, 80]),
+  ("Orange", [235, 150, 70]),
+  ("Yellow", [230, 210, 90]),
+  ("Green", [110, 190, 110]),
+  ("Blue", [90, 150, 230]),
+  ("Purple", [170, 110, 210]),
+  ("Gray", [160, 160, 160]),
+];
+
+/// Tags live in extended attributes where the filesystem has them. The
+/// database holds tags for everything else, and also mirrors the attribute
+/// tags so the sidebar can list tagged items across the whole disk.
+#[derive(Default)]
+pub struct TagStore {
+  db: Option<Connection>,
+  location: PathBuf,
+  /// Every tag with the paths carrying it, see `refresh`.
+  pub by_tag: BTreeMap<String, Vec<PathBuf>>,
+  /// Last known tags of listed paths, so a new listing has them right away
+  /// while the attributes are read again in the background.
+  known: HashMap<PathBuf, Vec<String>>,
+  receiver: Option<Receiver<Result<Synced, Error>>>,
+  /// Set when the listing changed, its attributes are read on the next frame.
+  pub stale: bool,
+  /// Text box for adding a new tag from the context menu.
+  pub input: String,
+}
+
+/// What a background read found, and whether it had to update the database.
+struct Synced {
+  tags: Vec<(PathBuf, Vec<String>)>,
+  written: bool,
+}
+
+impl TagStore {
+  pub fn open(location: &Path) -> Result<Self, Error> {
+    let mut store = Self {
+      db: Some(connect(location)?),
+      location: location.to_path_buf(),
+      ..Self::default()
+    };
+    store.refresh()?;
+    Ok(store)
+  }
+
+  /// The tags `path` had when it was last read, without touching the disk.
+  pub fn known(&self, path: &Path) -> Vec<String> {
+    self.known.get(path).cloned().unwrap_or_default()
+  }
+
+  /// Tags on `path`, preferring its extended attribute over the database.
+  pub fn read(&self, path: &Path) -> Vec<String> {
+    match (read_xattr(path), &self.db) {
+      (Ok(tags), _) if !tags.is_empty() => tags,
+      (_, Some(db)) => stored(db, path)
+        .map(|rows| rows.into_iter().map(|(tag, _)| tag).collect())
+        .unwrap_or_default(),
+      (_, None) => Vec::new(),
+    }
+  }
+
+  pub fn set(&mut self, path: &Path, tags: &[String]) -> Result<(), Error> {
+    let in_xattr = write_xattr(path, tags);
+    if let Some(db) = &self.db {
+      replace(db, path, tags, in_xattr)?;
+    }
+    self.known.insert(path.to_path_buf(), tags.to_vec());
+    self.refresh()
+  }
+
+  /// Adds `tag` to `path`, or takes it off when it is already there.
+  pub fn toggle(&mut self, path: &Path, tag: &str) -> Result<(), Error> {
+    let mut tags = self.read(path);
+    if let Some(index) = tags.iter().position(|existing| existing == tag) {
+      tags.remove(index);
+    } else {
+      tags.push(tag.to_owned());
+    }
+    self.set(path, &tags)
+  }
+
+  pub fn refresh(&mut self) -> Result<(), Error> {
+    let db = match &self.db {
+      Some(db) => db,
+      None => return Ok(()),
+    };
+    let mut statement = db
+      .prepare("SELECT tag, path FROM tags ORDER BY tag, path")
+      .map_err(database)?;
+    let rows = statement
+      .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))
+      .map_err(database)?;
+    let mut by_tag: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for row in rows {
+      let (tag, path) = row.map_err(database)?;
+      by_tag.entry(tag).or_default().push(path_from_bytes(path));
+    }
+    drop(statement);
+    self.by_tag = by_tag;
+    Ok(())
+  }
+}
+
+/// Reads the attributes of everything listed on a background thread once
+/// the listing changed, and puts the tags on the entries when it's done.
+pub fn poll(state: &mut Themis, ctx: &egui::Context) {
+  if let Some(receiver) = &state.tags.receiver {
+    match receiver.try_recv() {
+      Ok(result) => {
+        state.tags.receiver = None;
+        match result {
+          Ok(synced) => {
+            state.tags.known.extend(synced.tags);
+            annotate(state);
+            if synced.written {
+              if let Err(err) = state.tags.refresh() {
+                state.errors.report(err);
+              }
+            }
+          }
+          Err(err) => state.errors.report(err),
+        }
+      }
+      Err(crossbeam_channel::TryRecvError::Empty) => return,
+      Err(crossbeam_channel::TryRecvError::Disconnected) => state.tags.receiver = None,
+    }
+  }
+  if !state.tags.stale || state.tags.db.is_none() {
+    return;
+  }
+  state.tags.stale = false;
+
+  let paths: Vec<PathBuf> = state
+    .dir_entries
+    .iter()
+    .chain(state.search_results.iter())
+    .map(|entry| entry.path.clone())
+    .collect();
+  let location = state.tags.location.clone();
+  let (sender, receiver) = bounded(1);
+  let ctx = ctx.clone();
+  thread::spawn(move || {
+    let _ = sender.send(sync(&location, paths));
+    ctx.request_repaint();
+  });
+  state.tags.receiver = Some(receiver);
+}
+
+fn annotate(state: &mut Themis) {
+  let known = &state.tags.known;
+  for entry in state.dir_entries.iter_mut().chain(state.search_results.iter_mut()) {
+    if let Some(tags) = known.get(&entry.path) {
+      entry.tags = tags.clone();
+    }
+  }
+}
+
+/// Brings the database in line with the attributes of `paths`, writing only
+/// where they differ. Attribute tags removed by another tool are dropped,
+/// tags that only ever lived in the database are kept.
+fn sync(location: &Path, paths: Vec<PathBuf>) -> Result<Synced, Error> {
+  let mut db = connect(location)?;
+  let transaction = db.transaction().map_err(database)?;
+  let mut synced = Synced {
+    tags: Vec::with_capacity(paths.len()),
+    written: false,
+  };
+  for path in paths {
+    let stored = stored(&transaction, &path)?;
+    let tags = match read_xattr(&path) {
+      Ok(tags) if !tags.is_empty() => {
+        let mut sorted = tags.clone();
+        sorted.sort();
+        let unchanged = stored.len() == sorted.len()
+          && stored
+            .iter()
+            .zip(&sorted)
+            .all(|((stored, in_xattr), tag)| *in_xattr && stored == tag);
+        if !unchanged {
+          replace(&transaction, &path, &tags, true)?;
+          synced.written = true;
+        }
+        tags
+      }
+      // * No attribute where there was one means another tool removed it
+      Ok(_) if stored.iter().any(|(_, in_xattr)| *in_xattr) => {
+        transaction
+          .execute(
+            "DELETE FROM tags WHERE path = ?1 AND in_xattr = 1",
+            params![key(&path)],
+          )
+          .map_err(database)?;
+        synced.written = true;
+        stored
+          .into_iter()
+          .filter(|(_, in_xattr)| !in_xattr)
+          .map(|(tag, _)| tag)
+          .collect()
+      }
+      _ => stored.into_iter().map(|(tag, _)| tag).collect(),
+    };
+    synced.tags.push((path, tags));
+  }
+  transaction.commit().map_err(database)?;
+  Ok(synced)
+}
+
+fn connect(location: &Path) -> Result<Connection, Error> {
+  let db = Connection::open(location.join("tags.sqlite")).map_err(database)?;
+  // * The window and the background sync write through separate connections
+  db.busy_timeout(BUSY_TIMEOUT).map_err(database)?;
+  db.execute_batch(
+    "CREATE TABLE IF NOT EXISTS tags (
+      path BLOB NOT NULL,
+      tag TEXT NOT NULL,
+      in_xattr INTEGER NOT NULL DEFAULT 0,
+      PRIMARY KEY (path, tag)
+    );",
+  )
+  .map_err(database)?;
+  Ok(db)
+}
+
+/// Tags stored for `path` with whether they came from its attribute.
+fn stored(db: &Connection, path: &Path) -> Result<Vec<(String, bool)>, Error> {
+  let mut statement = db
+    .prepare_cached("SELECT tag, in_xattr FROM tags WHERE path = ?1 ORDER BY tag")
+    .map_err(database)?;
+  let rows = statement
+    .query_map(params![key(path)], |row| Ok((row.get(0)?, row.get(1)?)))
+    .map_err(database)?;
+  rows.collect::<Result<_, _>>().map_err(database)
+}
+
+fn replace(db: &Connection, path: &Path, tags: &[String], in_xattr: bool) -> Result<(), Error> {
+  let key = key(path);
+  db.execute("DELETE FROM tags WHERE path = ?1", params![key])
+    .map_err(database)?;
+  for tag in tags {
+    db.execute(
+      "INSERT OR IGNORE INTO tags (path, tag, in_xattr) VALUES (?1, ?2, ?3)",
+      params![key, tag, in_xattr],
+    )
+    .map_err(database)?;
+  }
+  Ok(())
+}
+
+/// Toggles `tag` on `path` and updates the listed entries to match.
+pub fn toggle(state: &mut Themis, path: &Path, tag: &str) {
+  if let Err(err) = state.tags.toggle(path, tag) {
+    state.errors.report(err);
+  }
+  let tags = state.tags.known(path);
+  for entry in state
+    .dir_entries
+    .iter_mut()
+    .chain(state.search_results.iter_mut())
+    .filter(|entry| entry.path == path)
+  {
+    entry.tags = tags.clone();
+  }
+}
+
+/// Chip color for `tag`, fixed for color labels and derived from the name
+/// for everything else.
+pub fn color(tag: &str) -> [u8; 3] {
+  if let Some((_, color)) = COLOR_LABELS
+    .iter()
+    .find(|(label, _)| label.eq_ignore_ascii_case(tag))
+  {
+    return *color;
+  }
+  let hash = tag
+    .bytes()
+    .fold(5381u32, |hash, byte| hash.wrapping_mul(33) ^ byte as u32);
+  let [r, g, b, _] = hash.to_le_bytes();
+  // * Keep it light enough for black text
+  [r / 2 + 110, g / 2 + 110, b / 2 + 110]
+}
+
+/// The attribute tags of `path`, empty when it has none and an error where
+/// the filesystem doesn't do attributes at all.
+fn read_xattr(path: &Path) -> std::io::Result<Vec<String>> {
+  let value = xattr::get(path, XATTR)?.unwrap_or_default();
+  Ok(
+    String::from_utf8_lossy(&value)
+      .split(',')
+      .map(str::trim)
+      .filter(|tag| !tag.is_empty())
+      .map(str::to_owned)
+      .collect(),
+  )
+}
+
+/// Best effort, anything the filesystem refuses is still in the database.
+/// Returns whether the attribute now holds the tags.
+fn write_xattr(path: &Path, tags: &[String]) -> bool {
+  if tags.is_empty() {
+    // * Nothing left to keep in sync with
+    let _ = xattr::remove(path, XATTR);
+    false
+  } else {
+    xattr::set(path, XATTR, tags.join(",").as_bytes()).is_ok()
+  }
+}
+
+fn key(path: &Path) -> Vec<u8> {
+  path_bytes(path)
+}
+
+fn database(err: rusqlite::Error) -> Error {
+  Error::Tags(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  #[test]
+  fn removed_attributes_are_forgotten() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    let file = dir.join("notes.txt");
+    std::fs::write(&file, "").unwrap();
+    // * Not every filesystem the tests run on has user attributes
+    if xattr::set(&file, XATTR, b"Blue,work").is_ok() {
+      let synced = sync(dir, vec![file.clone()]).unwrap();
+      assert!(synced.written);
+      assert_eq!(synced.tags[0].1, vec!["Blue".to_owned(), "work".to_owned()]);
+      assert!(!sync(dir, vec![file.clone()]).unwrap().written);
+
+      xattr::remove(&file, XATTR).unwrap();
+      let synced = sync(dir, vec![file.clone()]).unwrap();
+      assert!(synced.written);
+      assert!(synced.tags[0].1.is_empty());
+      assert!(TagStore::open(dir).unwrap().by_tag.is_empty());
+    }
+  }
+
+  #[test]
+  fn writes_wait_for_a_running_sync() {
+    let scratch = scratch();
+    let dir = scratch.path();
+    let mut sync = connect(dir).unwrap();
+    let transaction = sync.transaction().unwrap();
+    replace(&transaction, Path::new("/a"), &["Red".to_owned()], false).unwrap();
+    let writer = {
+      let dir = dir.to_path_buf();
+      thread::spawn(move || replace(&connect(&dir).unwrap(), Path::new("/b"), &["Blue".to_owned()], false))
+    };
+    thread::sleep(Duration::from_millis(200));
+    transaction.commit().unwrap();
+    assert!(writer.join().unwrap().is_ok());
+  }
+}
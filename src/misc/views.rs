@@ -0,0 +1,235 @@
+//! How each directory is shown: sorting, layout, zoom and filters. Views
+//! are kept in `views.sqlite` in the save location, and a directory without
+//! one of its own uses the closest parent's.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+use crate::app::{Error, Themis};
+use crate::misc::filters::ListingFilter;
+use crate::misc::name::path_bytes;
+use crate::ui::settings::SortMode;
+
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum ViewMode {
+  List,
+  Grid,
+}
+
+/// What the list view shows next to the name.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Columns {
+  pub size: bool,
+  /// File counts for directories, the type description for files.
+  pub details: bool,
+  pub git: bool,
+  pub tags: bool,
+}
+
+impl Default for Columns {
+  fn default() -> Self {
+    Self {
+      size: true,
+      details: true,
+      git: true,
+      tags: true,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct View {
+  pub sort_mode: SortMode,
+  pub sort_descending: bool,
+  pub mode: ViewMode,
+  pub columns: Columns,
+  pub zoom: f32,
+  pub filter: ListingFilter,
+  /// Whether subfolders without a view of their own use this one.
+  pub inherit: bool,
+}
+
+impl Default for View {
+  fn default() -> Self {
+    Self {
+      sort_mode: SortMode::Name,
+      sort_descending: false,
+      mode: ViewMode::List,
+      columns: Columns::default(),
+      zoom: 1.0,
+      filter: ListingFilter::default(),
+      inherit: true,
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct Views {
+  db: Option<Connection>,
+  /// The view as it was when we entered the directory, only a changed one
+  /// gets stored.
+  pub loaded: View,
+  /// Where the current view comes from, `None` for the default.
+  pub source: Option<PathBuf>,
+}
+
+impl Views {
+  pub fn open(location: &Path) -> Result<Self, Error> {
+    let db = Connection::open(location.join("views.sqlite")).map_err(database)?;
+    db.execute(
+      "CREATE TABLE IF NOT EXISTS views (
+        path BLOB PRIMARY KEY,
+        view TEXT NOT NULL
+      )",
+      [],
+    )
+    .map_err(database)?;
+    Ok(Self {
+      db: Some(db),
+      ..Self::default()
+    })
+  }
+
+  /// The view for `dir`, with the directory it was stored for.
+  pub fn lookup(&self, dir: &Path) -> Result<(View, Option<PathBuf>), Error> {
+    for (depth, ancestor) in dir.ancestors().enumerate() {
+      if let Some(view) = self.stored(ancestor)? {
+        if depth == 0 || view.inherit {
+          return Ok((view, Some(ancestor.to_path_buf())));
+        }
+      }
+    }
+    Ok((View::default(), None))
+  }
+
+  pub fn store(&self, dir: &Path, view: &View) -> Result<(), Error> {
+    let db = match &self.db {
+      Some(db) => db,
+      None => return Ok(()),
+    };
+    let json = serde_json::to_string(view).map_err(|err| Error::Views(err.to_string()))?;
+    db.execute(
+      "INSERT OR REPLACE INTO views (path, view) VALUES (?1, ?2)",
+      params![key(dir), json],
+    )
+    .map_err(database)?;
+    Ok(())
+  }
+
+  pub fn remove(&self, dir: &Path) -> Result<(), Error> {
+    if let Some(db) = &self.db {
+      db.execute("DELETE FROM views WHERE path = ?1", params![key(dir)])
+        .map_err(database)?;
+    }
+    Ok(())
+  }
+
+  fn stored(&self, dir: &Path) -> Result<Option<View>, Error> {
+    let db = match &self.db {
+      Some(db) => db,
+      None => return Ok(None),
+    };
+    let json: Option<String> = db
+      .query_row("SELECT view FROM views WHERE path = ?1", params![key(dir)], |row| row.get(0))
+      .optional()
+      .map_err(database)?;
+    // * A view from an older version that no longer parses is just skipped
+    Ok(json.and_then(|json| serde_json::from_str(&json).ok()))
+  }
+}
+
+/// Keeps the view of the directory we're leaving if it was changed, and
+/// brings up the one for the directory we're entering.
+pub fn switch(state: &mut Themis, from: &Path, to: &Path) -> Result<(), Error> {
+  let saved = save(state, from);
+  load(state, to)?;
+  saved
+}
+
+/// Stores the current view for `dir` if it differs from what was loaded.
+/// A change to the zoom alone goes to the view it was inherited from, so
+/// zooming around doesn't leave a view behind in every folder.
+pub fn save(state: &mut Themis, dir: &Path) -> Result<(), Error> {
+  let mut view = state.view.clone();
+  view.filter.quick = String::new();
+  if view == state.views.loaded {
+    return Ok(());
+  }
+  let only_zoom = View {
+    zoom: state.views.loaded.zoom,
+    ..view.clone()
+  } == state.views.loaded;
+  let target = match &state.views.source {
+    Some(source) if only_zoom => source.clone(),
+    _ => dir.to_path_buf(),
+  };
+  state.views.store(&target, &view)?;
+  state.views.loaded = view;
+  state.views.source = Some(target);
+  Ok(())
+}
+
+pub fn load(state: &mut Themis, dir: &Path) -> Result<(), Error> {
+  let (view, source) = state.views.lookup(dir)?;
+  state.view = view.clone();
+  state.views.loaded = view;
+  state.views.source = source;
+  Ok(())
+}
+
+/// Forgets the current directory's own view, going back to the inherited
+/// or default one.
+pub fn reset(state: &mut Themis) -> Result<(), Error> {
+  let dir = state.current_path.clone();
+  state.views.remove(&dir)?;
+  load(state, &dir)
+}
+
+fn key(path: &Path) -> Vec<u8> {
+  path_bytes(path)
+}
+
+fn database(err: rusqlite::Error) -> Error {
+  Error::Views(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  #[cfg(unix)]
+  #[test]
+  fn views_are_found_by_raw_bytes() {
+    use std::os::unix::ffi::OsStrExt;
+    let scratch = scratch();
+    let dir = scratch.path();
+    let grid = View {
+      mode: ViewMode::Grid,
+      ..View::default()
+    };
+    let views = Views::open(dir).unwrap();
+    let photos = Path::new("/home/me").join(std::ffi::OsStr::from_bytes(b"Fotos \xE9t\xE9"));
+    views.store(&photos, &grid).unwrap();
+    // * A lossy key would make this sibling share the view
+    views.store(Path::new("/home/me/Fotos \u{FFFD}t\u{FFFD}"), &View::default()).unwrap();
+
+    let (view, source) = views.lookup(&photos.join("2024")).unwrap();
+    assert_eq!(view, grid);
+    assert_eq!(source, Some(photos));
+  }
+
+  #[test]
+  fn the_quick_filter_is_not_stored() {
+    let mut view = View::default();
+    view.filter.quick = "notes".to_owned();
+    let json = serde_json::to_string(&view).unwrap();
+    let stored: View = serde_json::from_str(&json).unwrap();
+    assert_eq!(stored, View::default());
+  }
+}
@@ -0,0 +1,329 @@
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
+use eframe::egui;
+use notify::event::{EventKind, Flag, ModifyKind, RenameMode};
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::app::{DirWatcherEvent, Error, Themis};
+use crate::misc::search::{read_entry, sort_entries, update_current_dir};
+
+/// How long the watcher has to stay quiet before queued events are applied.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+/// Upper bound on the wait, so a constantly busy directory still refreshes.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Starts the watcher thread on `path`. Events come out of the returned
+/// receiver, watch requests go into the returned sender, and dropping that
+/// sender shuts the thread down.
+pub fn spawn(
+  path: PathBuf,
+  ctx: egui::Context,
+  errors: Sender<Error>,
+) -> (
+  Receiver<Event>,
+  Sender<(DirWatcherEvent, PathBuf)>,
+  thread::JoinHandle<()>,
+) {
+  let (event_sender, event_receiver) = unbounded();
+  let (request_sender, request_receiver) = unbounded();
+  let handle = thread::spawn(move || run(path, ctx, event_sender, request_receiver, errors));
+  (event_receiver, request_sender, handle)
+}
+
+fn run(
+  path: PathBuf,
+  ctx: egui::Context,
+  events: Sender<Event>,
+  requests: Receiver<(DirWatcherEvent, PathBuf)>,
+  errors: Sender<Error>,
+) {
+  let (tx, rx) = unbounded();
+  let watch_errors = errors.clone();
+  let watch_path = path.clone();
+  let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+    Ok(event) => {
+      let _ = tx.send(event);
+    }
+    Err(source) => {
+      let path = source.paths.first().cloned().unwrap_or_else(|| watch_path.clone());
+      let _ = watch_errors.send(Error::Watch { path, source });
+    }
+  });
+  let mut watcher = match watcher {
+    Ok(watcher) => watcher,
+    Err(source) => {
+      let _ = errors.send(Error::Watch { path, source });
+      return;
+    }
+  };
+  if let Err(source) = watcher.watch(&path, RecursiveMode::Recursive) {
+    let _ = errors.send(Error::Watch { path, source });
+  }
+
+  loop {
+    select! {
+      recv(rx) -> event => match event {
+        Ok(event) => {
+          if events.send(event).is_err() {
+            break;
+          }
+          // * The UI sleeps while idle, wake it so the change shows up
+          ctx.request_repaint();
+        }
+        Err(_) => break,
+      },
+      recv(requests) -> request => match request {
+        Ok((DirWatcherEvent::Add, path)) => {
+          if let Err(source) = watcher.watch(&path, RecursiveMode::Recursive) {
+            let _ = errors.send(Error::Watch { path, source });
+          }
+        }
+        Ok((DirWatcherEvent::Remove, path)) => {
+          // * The directory may be gone already, which is what we wanted anyway
+          let _ = watcher.unwatch(&path);
+        }
+        Err(_) => break,
+      },
+    }
+  }
+}
+
+/// Pulls everything the watcher thread sent since last frame into the
+/// pending batch and applies the batch once it has settled.
+pub fn poll(state: &mut Themis) -> Result<(), Error> {
+  let now = Instant::now();
+  for event in state.dir_watcher.dir_watcher.try_iter() {
+    if event.kind.is_access() {
+      continue;
+    }
+    state.dir_watcher.pending.push(event);
+    state.dir_watcher.first_event.get_or_insert(now);
+    state.dir_watcher.last_event = Some(now);
+  }
+  let settled = match (state.dir_watcher.first_event, state.dir_watcher.last_event) {
+    (Some(first), Some(last)) => settled(first, last, now),
+    _ => return Ok(()),
+  };
+  if !settled {
+    return Ok(());
+  }
+  state.dir_watcher.first_event = None;
+  state.dir_watcher.last_event = None;
+  let events = std::mem::take(&mut state.dir_watcher.pending);
+  apply(state, events)
+}
+
+/// Whether a batch that started at `first` and last grew at `last` should be
+/// applied by `now`.
+fn settled(first: Instant, last: Instant, now: Instant) -> bool {
+  now.saturating_duration_since(last) >= DEBOUNCE
+    || now.saturating_duration_since(first) >= MAX_DELAY
+}
+
+/// Whether a batch is still waiting for the watcher to go quiet.
+pub fn is_pending(state: &Themis) -> bool {
+  !state.dir_watcher.pending.is_empty()
+}
+
+fn apply(state: &mut Themis, events: Vec<Event>) -> Result<(), Error> {
+  state.git.stale = true;
+  state.tags.stale = true;
+  let mut changed = BTreeSet::new();
+  let mut moves = Vec::new();
+  for event in events {
+    if matches!(event.flag(), Some(Flag::Rescan)) {
+      return update_current_dir(state);
+    }
+    match event.kind {
+      EventKind::Access(_) => {}
+      EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+        moves.push((event.paths[0].clone(), event.paths[1].clone()));
+        changed.extend(event.paths);
+      }
+      // * Renames report the old and the new path, both need patching
+      EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_) => {
+        changed.extend(event.paths);
+      }
+      EventKind::Any | EventKind::Other => {
+        if event.paths.is_empty() {
+          return update_current_dir(state);
+        }
+        changed.extend(event.paths);
+      }
+    }
+  }
+
+  if !state.filesystem.files.is_empty() {
+    // * A moved directory takes its totals along instead of starting over
+    let mut moved = BTreeSet::new();
+    for (from, to) in moves {
+      if state.dir_sizes.move_dir(&from, &to) {
+        moved.insert(from);
+        moved.insert(to);
+      }
+    }
+    for path in changed.iter().filter(|path| !moved.contains(*path)) {
+      track_size(state, path);
+    }
+  }
+
+  let mut children = BTreeSet::new();
+  for path in changed {
+    if let Some(child) = direct_child(&state.current_path, &path) {
+      children.insert(child);
+    } else if path == state.current_path {
+      // * The directory itself changed, it may have been moved or removed
+      return update_current_dir(state);
+    }
+  }
+  for child in children {
+    patch(state, child);
+  }
+  sort_entries(&mut state.dir_entries, &state.view);
+  Ok(())
+}
+
+/// Keeps the recursive directory totals in line with what changed on disk.
+fn track_size(state: &mut Themis, path: &Path) {
+  match std::fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_dir() => state.dir_sizes.add_dir(path),
+    Ok(metadata) => state
+      .dir_sizes
+      .update_file(&state.filesystem, path, Some(metadata.len())),
+    Err(_) => {
+      if state.dir_sizes.dirs.contains_key(path) {
+        state.dir_sizes.remove_dir(path);
+      } else {
+        state.dir_sizes.update_file(&state.filesystem, path, None);
+      }
+    }
+  }
+}
+
+/// Maps a path anywhere below `dir` to the entry of `dir` that contains it.
+fn direct_child(dir: &Path, path: &Path) -> Option<PathBuf> {
+  let relative = path.strip_prefix(dir).ok()?;
+  let first = relative.components().next()?;
+  Some(dir.join(first))
+}
+
+fn patch(state: &mut Themis, path: PathBuf) {
+  let exists = std::fs::symlink_metadata(&path).is_ok();
+  let position = state.dir_entries.iter().position(|entry| entry.path == path);
+  match (exists, position) {
+    (true, Some(index)) => state.dir_entries[index] = read_entry(state, path),
+    (true, None) => {
+      let entry = read_entry(state, path);
+      state.dir_entries.push(entry);
+    }
+    (false, Some(index)) => {
+      state.dir_entries.remove(index);
+    }
+    (false, None) => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::misc::scratch;
+
+  #[test]
+  fn a_burst_waits_for_quiet() {
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    assert!(!settled(start, start, start));
+    // * Events at 0 and 100ms, the batch goes 150ms after the last one
+    assert!(!settled(start, at(100), at(200)));
+    assert!(settled(start, at(100), at(250)));
+  }
+
+  #[test]
+  fn a_busy_directory_still_refreshes() {
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    // * Something changes every 100ms, the window never goes quiet
+    assert!(!settled(start, at(900), at(950)));
+    assert!(settled(start, at(1000), at(1000)));
+  }
+
+  /// A state listing `dir`, plus an entry that isn't on disk. Patching
+  /// leaves it alone, only a full reload drops it.
+  fn listing(dir: &Path) -> (Themis, Receiver<(DirWatcherEvent, PathBuf)>) {
+    let mut state = Themis::default();
+    let (updater, updates) = unbounded();
+    state.dir_watcher.watcher_updater = updater;
+    state.current_path = dir.to_path_buf();
+    update_current_dir(&mut state).unwrap();
+    state.dir_entries.push(crate::app::DirEntry {
+      path: dir.join("ghost"),
+      name: "ghost".into(),
+      ..Default::default()
+    });
+    (state, updates)
+  }
+
+  fn listed(state: &Themis) -> Vec<String> {
+    let mut names: Vec<String> =
+      state.dir_entries.iter().map(|entry| entry.display_name()).collect();
+    names.sort();
+    names
+  }
+
+  fn event(kind: EventKind, paths: &[PathBuf]) -> Event {
+    paths.iter().fold(Event::new(kind), |event, path| event.add_path(path.clone()))
+  }
+
+  #[test]
+  fn renames_and_removals_patch_the_listing() {
+    let scratch = scratch();
+    let dir = scratch.path().canonicalize().unwrap();
+    std::fs::write(dir.join("a.txt"), "").unwrap();
+    std::fs::write(dir.join("b.txt"), "").unwrap();
+    let (mut state, _updates) = listing(&dir);
+    assert_eq!(listed(&state), ["a.txt", "b.txt", "ghost"]);
+
+    std::fs::rename(dir.join("a.txt"), dir.join("c.txt")).unwrap();
+    let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+    apply(&mut state, vec![event(rename, &[dir.join("a.txt"), dir.join("c.txt")])]).unwrap();
+    assert_eq!(listed(&state), ["b.txt", "c.txt", "ghost"]);
+
+    std::fs::remove_file(dir.join("b.txt")).unwrap();
+    let remove = EventKind::Remove(notify::event::RemoveKind::File);
+    apply(&mut state, vec![event(remove, &[dir.join("b.txt")])]).unwrap();
+    assert_eq!(listed(&state), ["c.txt", "ghost"]);
+
+    // * Changes deeper down only refresh the entry that contains them
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub").join("d.txt"), "").unwrap();
+    let create = EventKind::Create(notify::event::CreateKind::File);
+    apply(&mut state, vec![event(create, &[dir.join("sub").join("d.txt")])]).unwrap();
+    assert_eq!(listed(&state), ["c.txt", "ghost", "sub"]);
+    assert!(!state.dir_entries.iter().any(|entry| entry.name == "d.txt"));
+  }
+
+  #[test]
+  fn a_rescan_reloads_everything() {
+    let scratch = scratch();
+    let dir = scratch.path().canonicalize().unwrap();
+    std::fs::write(dir.join("a.txt"), "").unwrap();
+    let (mut state, _updates) = listing(&dir);
+    let rescan = Event::new(EventKind::Other).set_flag(Flag::Rescan);
+    apply(&mut state, vec![rescan]).unwrap();
+    assert_eq!(listed(&state), ["a.txt"]);
+  }
+
+  #[test]
+  fn dropping_the_sender_stops_the_thread() {
+    let scratch = scratch();
+    let (errors, _errors) = unbounded();
+    let (events, requests, handle) =
+      spawn(scratch.path().to_path_buf(), egui::Context::default(), errors);
+    drop(requests);
+    handle.join().unwrap();
+    drop(events);
+  }
+}
@@ -0,0 +1,135 @@
+use eframe::egui;
+
+use crate::app::Themis;
+use crate::misc::name::escape;
+
+/// The bookmarks section of the sidebar.
+pub fn sidebar(ui: &mut egui::Ui, state: &mut Themis) {
+  ui.horizontal(|ui| {
+    ui.heading("Bookmarks:");
+    if ui.small_button("Import").on_hover_text("Add bookmarks from GTK apps").clicked() {
+      if let Err(err) = state.bookmarks.import() {
+        state.errors.report(err);
+      }
+    }
+    if ui.small_button("Export").on_hover_text("Share bookmarks with GTK apps").clicked() {
+      if let Err(err) = state.bookmarks.export() {
+        state.errors.report(err);
+      }
+    }
+  });
+
+  let order = state.bookmarks.display_order();
+  let released = ui.input().pointer.any_released();
+  let mut dropped = None;
+  let mut group: Option<String> = None;
+  let mut group_open = true;
+  for (position, index) in order.into_iter().enumerate() {
+    let bookmark = state.bookmarks.items[index].clone();
+    if !bookmark.group.is_empty() && group.as_ref() != Some(&bookmark.group) {
+      // * A header per group, its rows follow until the next group starts
+      let id = ui.make_persistent_id(("bookmark_group", &bookmark.group));
+      let mut open = ui.memory().data.get_persisted::<bool>(id).unwrap_or(true);
+      let arrow = if open { "⏷" } else { "⏵" };
+      if ui.selectable_label(false, format!("{} {}", arrow, bookmark.group)).clicked() {
+        open = !open;
+        ui.memory().data.insert_persisted(id, open);
+      }
+      group = Some(bookmark.group.clone());
+      group_open = open;
+    }
+    if !group_open {
+      continue;
+    }
+
+    let missing = !bookmark.path.exists();
+    let mut text = egui::RichText::new(format!("{} {}", bookmark.icon, bookmark.name));
+    if missing {
+      text = egui::RichText::new(format!("⚠ {}", bookmark.name)).color(egui::Color32::RED);
+    }
+    let mut hover = escape(bookmark.path.as_os_str());
+    if missing {
+      hover = format!("Missing: {}", hover);
+    }
+    if position < 9 {
+      hover = format!("{}\nCtrl+{}", hover, position + 1);
+    }
+    let row = ui.horizontal(|ui| {
+      if !bookmark.group.is_empty() {
+        ui.add_space(12.0);
+      }
+      ui.add(egui::Button::new(text).sense(egui::Sense::click_and_drag()))
+    });
+    let button = row.inner.on_hover_text(hover);
+    if button.drag_started() {
+      state.bookmarks.dragging = Some(index);
+    }
+    if let Some(dragging) = state.bookmarks.dragging {
+      ui.output().cursor_icon = egui::CursorIcon::Grabbing;
+      if dragging != index && ui.rect_contains_pointer(row.response.rect) {
+        // * Mark where the bookmark would land
+        let rect = row.response.rect;
+        let stroke = ui.visuals().selection.stroke;
+        ui.painter().line_segment([rect.left_top(), rect.right_top()], stroke);
+        if released {
+          dropped = Some((dragging, index));
+        }
+      }
+    }
+    if button.clicked() && !missing {
+      state.current_path = bookmark.path.clone();
+    }
+    button.context_menu(|ui| {
+      if ui.button("Edit").clicked() {
+        state.bookmarks.editing = Some(index);
+        ui.close_menu();
+      }
+      if ui.button("Remove").clicked() {
+        state.bookmarks.remove(&bookmark.path);
+        ui.close_menu();
+      }
+    });
+  }
+  if let Some((from, to)) = dropped {
+    state.bookmarks.move_to(from, to);
+  }
+  if released {
+    state.bookmarks.dragging = None;
+  }
+}
+
+/// Name, icon and group editing for a single bookmark.
+pub fn editor(ctx: &egui::Context, state: &mut Themis) {
+  let index = match state.bookmarks.editing {
+    Some(index) if index < state.bookmarks.items.len() => index,
+    _ => return,
+  };
+  let mut open = true;
+  egui::Window::new("Edit bookmark")
+    .open(&mut open)
+    .collapsible(false)
+    .show(ctx, |ui| {
+      let bookmark = &mut state.bookmarks.items[index];
+      egui::Grid::new("bookmark_editor").show(ui, |ui| {
+        ui.label("Name");
+        ui.text_edit_singleline(&mut bookmark.name);
+        ui.end_row();
+        ui.label("Icon");
+        ui.text_edit_singleline(&mut bookmark.icon);
+        ui.end_row();
+        ui.label("Group");
+        ui.text_edit_singleline(&mut bookmark.group)
+          .on_hover_text("Bookmarks with the same group are shown together");
+        ui.end_row();
+        ui.label("Path");
+        ui.label(escape(bookmark.path.as_os_str()));
+        ui.end_row();
+      });
+      if ui.button("Done").clicked() {
+        state.bookmarks.editing = None;
+      }
+    });
+  if !open {
+    state.bookmarks.editing = None;
+  }
+}
@@ -0,0 +1,74 @@
+use bytesize::ByteSize;
+use eframe::egui;
+
+use crate::app::Themis;
+use crate::misc::cleanup::{self, Category};
+use crate::misc::name::escape;
+
+pub fn main(ctx: &egui::Context, state: &mut Themis) {
+  cleanup::poll(state);
+
+  egui::CentralPanel::default().show(ctx, |ui| {
+    ui.horizontal(|ui| {
+      let scanning = state.cleanup.scan.is_some();
+      if ui
+        .add_enabled(!scanning, egui::Button::new("Scan current directory"))
+        .clicked()
+      {
+        cleanup::scan(state, state.current_path.clone());
+      }
+      let has_findings = !state.cleanup.findings.is_empty();
+      if ui
+        .add_enabled(has_findings, egui::Button::new("Export report"))
+        .clicked()
+      {
+        match cleanup::export(state) {
+          Ok(path) => state.cleanup.exported = Some(path),
+          Err(err) => state.errors.report(err),
+        }
+      }
+      if let Some(root) = &state.cleanup.root {
+        ui.label(escape(root.as_os_str()));
+      }
+      if let Some(path) = &state.cleanup.exported {
+        ui.weak(format!("Report saved to {}", escape(path.as_os_str())));
+      }
+    });
+    if state.cleanup.scan.is_some() {
+      ui.label("Scanning...");
+      // * Keep polling until the scan thread is done
+      ctx.request_repaint();
+      return;
+    }
+    ui.separator();
+
+    let mut trash = None;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+      for category in Category::ALL {
+        let findings: Vec<_> = state.cleanup.of(category).cloned().collect();
+        let size: u64 = findings.iter().map(|finding| finding.size).sum();
+        let title = if size == 0 {
+          format!("{} ({})", category.label(), findings.len())
+        } else {
+          format!("{} ({}, {})", category.label(), findings.len(), ByteSize(size))
+        };
+        egui::CollapsingHeader::new(title)
+          .id_source(category.label())
+          .show(ui, |ui| {
+            if ui
+              .add_enabled(!findings.is_empty(), egui::Button::new("Move all to trash"))
+              .clicked()
+            {
+              trash = Some(category);
+            }
+            for finding in &findings {
+              ui.label(escape(finding.path.as_os_str()));
+            }
+          });
+      }
+    });
+    if let Some(category) = trash {
+      cleanup::trash_category(state, category);
+    }
+  });
+}
@@ -0,0 +1,121 @@
+use bytesize::ByteSize;
+use eframe::egui;
+use std::time::SystemTime;
+
+use crate::app::Themis;
+use crate::misc::conflict::{resolve_next, FileStat, Resolution};
+use crate::misc::name::escape;
+
+pub fn main(ctx: &egui::Context, state: &mut Themis) {
+  if state.conflicts.queue.is_empty() {
+    return;
+  }
+  let mut choice = None;
+  egui::Window::new("File already exists")
+    .collapsible(false)
+    .resizable(false)
+    .show(ctx, |ui| {
+      let remaining = state.conflicts.queue.len();
+      let conflict = &mut state.conflicts.queue[0];
+      ui.label(format!(
+        "{:?}: \"{}\" already exists in {}",
+        conflict.operation,
+        escape(conflict.target.file_name().unwrap_or_default()),
+        escape(conflict.target.parent().unwrap_or(&conflict.target).as_os_str()),
+      ));
+      ui.end_row();
+      egui::Grid::new("conflict_grid").show(ui, |ui| {
+        ui.label("");
+        ui.label("Source");
+        ui.label("Target");
+        ui.end_row();
+        ui.label("Size");
+        stat_label(ui, &conflict.source_stat, &conflict.target_stat, |stat| {
+          ByteSize(stat.size).to_string()
+        });
+        ui.end_row();
+        ui.label("Modified");
+        stat_label(ui, &conflict.source_stat, &conflict.target_stat, |stat| {
+          format_age(stat.modified)
+        });
+        ui.end_row();
+        if conflict.source_stat.hash.is_some() {
+          ui.label("Hash");
+          stat_label(ui, &conflict.source_stat, &conflict.target_stat, |stat| {
+            format!("{:016x}", stat.hash.unwrap_or_default())
+          });
+          ui.end_row();
+        }
+      });
+      if conflict.source_stat.hash.is_none()
+        && !conflict.source_stat.is_dir
+        && ui.button("Compare contents").clicked()
+      {
+        conflict.compare_contents();
+      }
+      if conflict.source_stat.hash.is_some() {
+        if conflict.source_stat.hash == conflict.target_stat.hash {
+          ui.label("The files are identical.");
+        } else {
+          ui.label("The files differ.");
+        }
+      }
+      ui.separator();
+      ui.horizontal(|ui| {
+        if ui.button("Overwrite").clicked() {
+          choice = Some(Resolution::Overwrite);
+        }
+        if ui.button("Skip").clicked() {
+          choice = Some(Resolution::Skip);
+        }
+        if ui.button("Keep both").clicked() {
+          choice = Some(Resolution::KeepBoth);
+        }
+      });
+      ui.horizontal(|ui| {
+        let rename_bar = ui.text_edit_singleline(&mut conflict.rename_value);
+        let submitted = rename_bar.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+        if (ui.button("Rename").clicked() || submitted) && !conflict.rename_value.is_empty() {
+          choice = Some(Resolution::Rename(conflict.rename_value.clone()));
+        }
+      });
+      if remaining > 1 {
+        ui.checkbox(
+          &mut state.conflicts.apply_to_all,
+          format!("Apply to all ({} remaining)", remaining),
+        );
+      }
+    });
+  if let Some(resolution) = choice {
+    resolve_next(state, resolution);
+  }
+}
+
+fn stat_label(
+  ui: &mut egui::Ui,
+  source: &FileStat,
+  target: &FileStat,
+  format: impl Fn(&FileStat) -> String,
+) {
+  let (source_text, target_text) = (format(source), format(target));
+  if source_text == target_text {
+    ui.label(source_text);
+    ui.label(target_text);
+  } else {
+    ui.colored_label(egui::Color32::LIGHT_YELLOW, source_text);
+    ui.colored_label(egui::Color32::LIGHT_YELLOW, target_text);
+  }
+}
+
+fn format_age(time: Option<SystemTime>) -> String {
+  let elapsed = match time.and_then(|time| time.elapsed().ok()) {
+    Some(elapsed) => elapsed.as_secs(),
+    None => return "unknown".to_owned(),
+  };
+  match elapsed {
+    0..=59 => format!("{} seconds ago", elapsed),
+    60..=3599 => format!("{} minutes ago", elapsed / 60),
+    3600..=86399 => format!("{} hours ago", elapsed / 3600),
+    _ => format!("{} days ago", elapsed / 86400),
+  }
+}
@@ -0,0 +1,161 @@
+use bytesize::ByteSize;
+use eframe::egui;
+use std::path::Path;
+
+use crate::app::{PanelOpen, Themis};
+use crate::misc::disk_usage::{self, squarify, UsageItem};
+use crate::misc::name::escape;
+
+pub fn main(ctx: &egui::Context, state: &mut Themis) {
+  disk_usage::poll(state);
+  if state.disk_usage.root.is_none() {
+    disk_usage::open(state, state.current_path.clone());
+  }
+  let root = state.disk_usage.root.clone().unwrap_or_default();
+
+  egui::SidePanel::left("side_panel").show(ctx, |ui| {
+    ui.heading("Largest");
+    egui::ScrollArea::vertical().show(ui, |ui| {
+      for item in state.disk_usage.items.clone() {
+        let fraction = if state.disk_usage.total == 0 {
+          0.0
+        } else {
+          item.size as f32 / state.disk_usage.total as f32
+        };
+        let label = ui.add(
+          egui::Label::new(format!("{} {}", icon(&item), escape(&item.name)))
+            .sense(egui::Sense::click()),
+        );
+        if label.clicked() && item.is_dir {
+          disk_usage::open(state, item.path.clone());
+        }
+        ui.add(egui::ProgressBar::new(fraction).text(format!(
+          "{} ({:.1}%)",
+          ByteSize(item.size),
+          fraction * 100.0
+        )));
+      }
+    });
+  });
+
+  egui::CentralPanel::default().show(ctx, |ui| {
+    ui.horizontal(|ui| {
+      if ui.button("Go up").clicked() {
+        if let Some(parent) = root.parent() {
+          disk_usage::open(state, parent.to_path_buf());
+        }
+      }
+      if ui.button("Rescan").clicked() {
+        disk_usage::open(state, root.clone());
+      }
+      if ui.button("Show in file menu").clicked() {
+        state.current_path = root.clone();
+        state.panel_open = PanelOpen::Main;
+      }
+      ui.label(format!(
+        "{} ({})",
+        escape(root.as_os_str()),
+        ByteSize(state.disk_usage.total)
+      ));
+    });
+    if state.disk_usage.scan.is_some() {
+      ui.label("Scanning...");
+      // * Keep polling until the scan thread is done
+      ctx.request_repaint();
+      return;
+    }
+    treemap(ui, state, &root);
+  });
+}
+
+fn treemap(ui: &mut egui::Ui, state: &mut Themis, root: &Path) {
+  let items: Vec<UsageItem> = state
+    .disk_usage
+    .items
+    .iter()
+    .filter(|item| item.size > 0)
+    .cloned()
+    .collect();
+  let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::click());
+  let sizes: Vec<u64> = items.iter().map(|item| item.size).collect();
+  let rects = squarify(&sizes, response.rect);
+
+  let font = egui::FontId::proportional(12.0);
+  let mut hovered = None;
+  for (index, (item, rect)) in items.iter().zip(rects.iter()).enumerate() {
+    let is_hovered = matches!(response.hover_pos(), Some(pos) if rect.contains(pos));
+    if is_hovered {
+      hovered = Some(index);
+    }
+    let mut color = color(item);
+    if is_hovered {
+      color = color.linear_multiply(1.4);
+    }
+    painter.rect_filled(rect.shrink(1.0), 2.0, color);
+    if rect.width() > 60.0 && rect.height() > 20.0 {
+      let text_painter = painter.sub_region(rect.shrink(2.0));
+      text_painter.text(
+        rect.left_top() + egui::vec2(4.0, 4.0),
+        egui::Align2::LEFT_TOP,
+        format!("{}\n{}", escape(&item.name), ByteSize(item.size)),
+        font.clone(),
+        egui::Color32::BLACK,
+      );
+    }
+  }
+
+  if let Some(index) = hovered {
+    let item = &items[index];
+    egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("treemap_tooltip"), |ui| {
+      ui.label(escape(item.path.as_os_str()));
+      ui.label(ByteSize(item.size).to_string());
+    });
+    if response.clicked() && item.is_dir {
+      disk_usage::open(state, item.path.clone());
+    }
+  }
+  if items.is_empty() {
+    painter.text(
+      response.rect.center(),
+      egui::Align2::CENTER_CENTER,
+      format!("{} is empty", escape(root.as_os_str())),
+      font,
+      ui.visuals().text_color(),
+    );
+  }
+}
+
+fn icon(item: &UsageItem) -> &'static str {
+  if item.is_dir {
+    "🗀"
+  } else {
+    "🗋"
+  }
+}
+
+/// Colors by rough file type, so similar files are easy to spot.
+fn color(item: &UsageItem) -> egui::Color32 {
+  if item.is_dir {
+    return egui::Color32::from_rgb(120, 160, 220);
+  }
+  let extension = Path::new(&item.name)
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default();
+  match extension.as_str() {
+    "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "svg" | "tiff" => {
+      egui::Color32::from_rgb(230, 160, 90)
+    }
+    "mp4" | "mkv" | "avi" | "mov" | "webm" => egui::Color32::from_rgb(220, 100, 110),
+    "mp3" | "flac" | "wav" | "ogg" | "m4a" => egui::Color32::from_rgb(190, 120, 210),
+    "zip" | "tar" | "gz" | "xz" | "bz2" | "7z" | "rar" | "zst" => {
+      egui::Color32::from_rgb(210, 200, 100)
+    }
+    "rs" | "c" | "h" | "cpp" | "py" | "js" | "ts" | "go" | "java" | "toml" | "json" => {
+      egui::Color32::from_rgb(120, 200, 140)
+    }
+    "pdf" | "doc" | "docx" | "odt" | "txt" | "md" => egui::Color32::from_rgb(120, 200, 210),
+    "exe" | "dll" | "so" | "bin" | "iso" | "img" => egui::Color32::from_rgb(170, 170, 170),
+    _ => egui::Color32::from_rgb(200, 200, 200),
+  }
+}
@@ -0,0 +1,69 @@
+use bytesize::ByteSize;
+use eframe::egui;
+
+use crate::app::Themis;
+use crate::misc::duplicates;
+use crate::misc::name::escape;
+
+pub fn main(ctx: &egui::Context, state: &mut Themis) {
+  duplicates::poll(state);
+
+  egui::CentralPanel::default().show(ctx, |ui| {
+    ui.horizontal(|ui| {
+      let running = state.duplicates.receiver.is_some();
+      if ui
+        .add_enabled(!running, egui::Button::new("Find in current directory"))
+        .clicked()
+      {
+        duplicates::find(state, Some(state.current_path.clone()));
+      }
+      if ui
+        .add_enabled(
+          !running && !state.filesystem.files.is_empty(),
+          egui::Button::new("Find in whole index"),
+        )
+        .clicked()
+      {
+        duplicates::find(state, None);
+      }
+      ui.label(&state.duplicates.status);
+    });
+    if state.duplicates.receiver.is_some() {
+      // * Keep polling until the search thread is done
+      ctx.request_repaint();
+    }
+    ui.separator();
+
+    let mut trash = None;
+    let mut hardlink = None;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+      for (index, group) in state.duplicates.groups.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+          ui.strong(format!(
+            "{} copies of {} ({} wasted)",
+            group.paths.len(),
+            ByteSize(group.size),
+            ByteSize(group.wasted())
+          ))
+          .on_hover_text(&group.hash);
+          if ui.button("Trash others").clicked() {
+            trash = Some(index);
+          }
+          if ui.button("Hardlink others").clicked() {
+            hardlink = Some(index);
+          }
+        });
+        for (position, path) in group.paths.iter().enumerate() {
+          ui.radio_value(&mut group.keep, position, escape(path.as_os_str()));
+        }
+        ui.separator();
+      }
+    });
+    if let Some(index) = trash {
+      duplicates::trash_others(state, index);
+    }
+    if let Some(index) = hardlink {
+      duplicates::hardlink_others(state, index);
+    }
+  });
+}
@@ -0,0 +1,63 @@
+use eframe::egui;
+use std::time::Duration;
+
+use crate::app::Themis;
+
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+pub fn toasts(ctx: &egui::Context, state: &mut Themis) {
+  let recent: Vec<&str> = state
+    .errors
+    .entries
+    .iter()
+    .rev()
+    .take_while(|entry| entry.shown_at.elapsed() < TOAST_DURATION)
+    .take(3)
+    .map(|entry| entry.message.as_str())
+    .collect();
+  if recent.is_empty() {
+    return;
+  }
+  egui::Area::new("error_toasts")
+    .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+    .show(ctx, |ui| {
+      for message in recent {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+          ui.set_max_width(300.0);
+          ui.colored_label(egui::Color32::LIGHT_RED, message);
+        });
+      }
+    });
+  // * Keep repainting so toasts disappear on time even when idle
+  ctx.request_repaint();
+}
+
+pub fn log(ctx: &egui::Context, state: &mut Themis) {
+  if !state.errors.open {
+    return;
+  }
+  egui::TopBottomPanel::bottom("error_log")
+    .resizable(true)
+    .show(ctx, |ui| {
+      ui.horizontal(|ui| {
+        ui.heading("Errors");
+        if ui.button("Clear").clicked() {
+          state.errors.entries.clear();
+        }
+        if ui.button("Close").clicked() {
+          state.errors.open = false;
+        }
+      });
+      egui::ScrollArea::vertical()
+        .stick_to_bottom()
+        .show(ui, |ui| {
+          for entry in &state.errors.entries {
+            ui.horizontal(|ui| {
+              let age = entry.time.elapsed().unwrap_or_default().as_secs();
+              ui.weak(format!("{}s ago", age));
+              ui.label(&entry.message);
+            });
+          }
+        });
+    });
+}
@@ -1,75 +1,50 @@
-use crate::app::Themis;
+use crate::app::{DirEntry, Error, Rename, Themis};
 use bytesize::ByteSize;
 use eframe::egui;
 
-use crate::misc::search::update_current_dir;
+use crate::misc::conflict::{self, Operation};
+use crate::misc::actions;
+use crate::misc::mime::FileType;
+use crate::misc::name::{escape, unescape};
+use crate::misc::open_with;
+use crate::misc::properties;
+use crate::misc::tags::{self, COLOR_LABELS};
+use crate::misc::views::ViewMode;
 
 pub fn file_menu(state: &mut Themis, ui: &mut egui::Ui) {
   ui.vertical(|ui| {
-    let dir_entries;
-    if state.search == "" {
-      dir_entries = state.dir_entries.clone();
+    let dir_entries = if state.search.is_empty() {
+      state
+        .dir_entries
+        .iter()
+        .filter(|entry| state.view.filter.matches(entry))
+        .cloned()
+        .collect::<Vec<_>>()
     } else {
-      dir_entries = state.search_results.clone();
+      state.search_results.clone()
+    };
+    let listed: Vec<std::path::PathBuf> =
+      dir_entries.iter().map(|entry| entry.path.clone()).collect();
+    if state.view.mode == ViewMode::Grid {
+      // * Fixed size tiles, wrapping at the edge of the panel
+      let tile = egui::vec2(110.0, 70.0) * state.view.zoom;
+      ui.horizontal_wrapped(|ui| {
+        for entry in dir_entries {
+          ui.allocate_ui(tile, |ui| {
+            ui.set_min_size(tile);
+            ui.vertical_centered(|ui| {
+              let icon = egui::RichText::new(entry.file_type.icon).size(32.0 * state.view.zoom);
+              ui.label(icon);
+              entry_row(state, ui, &entry, &listed, true);
+            });
+          });
+        }
+      });
+      return;
     }
     for entry in dir_entries {
       ui.horizontal(|ui| {
-        let name = entry.name.clone();
-        let path = entry.path.clone();
-        let is_dir = entry.path.is_dir();
-        let dir_size = ByteSize(entry.size);
-        let label = if is_dir {
-          format!("{}/", name)
-        } else {
-          name.to_owned()
-        };
-        let formatted = if is_dir {
-          if entry.is_empty {
-            format!("🗁 {} ({})", label, dir_size)
-          } else {
-            format!("🗀 {} ({})", label, dir_size)
-          }
-        } else {
-          format!("🗋 {} ({})", label, dir_size)
-        };
-        if state.rename.target.clone().unwrap_or_default() != path {
-          let thing = ui.add(egui::Label::new(formatted).sense(egui::Sense::click()));
-          if thing.double_clicked() {
-            if is_dir {
-              state.current_path = path.to_path_buf()
-            } else {
-              open::that(path.to_str().unwrap()).unwrap()
-            }
-          }
-          if thing.hovered() {
-            state.selected_path = path.to_path_buf();
-          }
-          thing.context_menu(|ui| {
-            context_menu(state, ui);
-          });
-        } else {
-          let rename_bar = ui.text_edit_singleline(&mut state.rename.value);
-          if rename_bar.lost_focus() {
-            if state.rename.value != "" {
-              let new_name = state.rename.value.clone();
-              let new_path = state
-                .rename
-                .target
-                .clone()
-                .unwrap()
-                .with_file_name(new_name);
-              std::fs::rename(state.rename.target.clone().unwrap(), new_path).unwrap();
-              update_current_dir(state);
-            }
-            state.rename.target = None;
-            state.rename.value = String::new();
-          } else {
-            rename_bar.request_focus();
-          }
-          if rename_bar.gained_focus() {
-            state.rename.value = name.to_owned();
-          }
-        }
+        entry_row(state, ui, &entry, &listed, false);
       });
       ui.end_row();
       ui.add(egui::Separator::spacing(
@@ -84,13 +59,268 @@ pub fn file_menu(state: &mut Themis, ui: &mut egui::Ui) {
     context_menu(state, ui);
   });
 
+  /// The name of an entry with whatever the view's columns ask for, or the
+  /// rename box while it's being renamed. Tiles only show the name.
+  fn entry_row(
+    state: &mut Themis,
+    ui: &mut egui::Ui,
+    entry: &DirEntry,
+    listed: &[std::path::PathBuf],
+    tile: bool,
+  ) {
+    let name = entry.display_name();
+    let path = entry.path.clone();
+    let is_dir = entry.path.is_dir();
+    let dir_size = ByteSize(entry.size);
+    let columns = state.view.columns;
+    let label = if is_dir {
+      format!("{}/", name)
+    } else {
+      name.to_owned()
+    };
+    let mut details = Vec::new();
+    if columns.size {
+      details.push(dir_size.to_string());
+    }
+    if columns.details {
+      match entry.stats {
+        Some(stats) => details.push(format!("{} files, {} folders", stats.files, stats.dirs)),
+        None => details.push(entry.file_type.description.clone()),
+      }
+    }
+    let formatted = if tile {
+      label
+    } else if details.is_empty() {
+      format!("{} {}", entry.file_type.icon, label)
+    } else {
+      format!("{} {} ({})", entry.file_type.icon, label, details.join(", "))
+    };
+    if state.rename.target.clone().unwrap_or_default() != path {
+      let selected = state.selection.contains(&path);
+      let mut thing = ui.add(egui::SelectableLabel::new(selected, formatted));
+      if let Some(stats) = entry.stats {
+        thing = thing.on_hover_text(format!("{} on disk", ByteSize(stats.allocated)));
+      } else if tile {
+        thing = thing.on_hover_text(format!("{}, {}", dir_size, entry.file_type.description));
+      }
+      if thing.double_clicked() {
+        if is_dir {
+          state.current_path = path.to_path_buf()
+        } else {
+          if let Err(err) = open::that(&path) {
+            state.errors.report(Error::io("open", &path, err));
+          }
+        }
+      }
+      if thing.clicked() {
+        let modifiers = ui.input().modifiers;
+        if modifiers.shift {
+          state.selection.extend_to(&path, listed);
+        } else if modifiers.command {
+          state.selection.toggle(&path);
+        } else {
+          state.selection.select(&path);
+        }
+      }
+      // * The context menu acts on what was right-clicked
+      if thing.secondary_clicked() {
+        state.menu_type = None;
+        if !selected {
+          state.selection.select(&path);
+        }
+      }
+      if thing.hovered() {
+        state.selected_path = path.to_path_buf();
+      }
+      if !tile {
+        if let Some(status) = entry.git.filter(|_| columns.git) {
+          ui.label(egui::RichText::new(status.badge()).strong().color(status.color()))
+            .on_hover_text(status.label());
+        }
+        if columns.tags {
+          for tag in &entry.tags {
+            tag_chip(ui, tag);
+          }
+        }
+      }
+      thing.context_menu(|ui| {
+        context_menu(state, ui);
+      });
+    } else {
+      let rename_bar = ui.text_edit_singleline(&mut state.rename.value);
+      if rename_bar.lost_focus() {
+        // * Untouched text leaves the exact original bytes alone
+        if state.rename.value != escape(&state.rename.original) {
+          let name = unescape(&state.rename.value);
+          match conflict::valid_name(&name) {
+            Ok(()) => {
+              let target = path.clone();
+              let new_path = target.with_file_name(name);
+              conflict::reset(state);
+              conflict::transfer(state, Operation::Rename, target, new_path);
+            }
+            Err(err) => state.errors.report(err),
+          }
+        }
+        state.rename = Rename::default();
+      } else {
+        rename_bar.request_focus();
+      }
+    }
+  }
+
   fn context_menu(state: &mut Themis, ui: &mut egui::Ui) {
     if ui.button("Print Name").clicked() {
       println!("{:?}", state.selected_path);
     }
     if ui.button("Rename").clicked() {
-      state.rename.target = Some(state.selected_path.clone());
+      let original = state.selected_path.file_name().unwrap_or_default().to_os_string();
+      state.rename = Rename {
+        value: escape(&original),
+        target: Some(state.selected_path.clone()),
+        original,
+      };
+      ui.close_menu();
+    }
+    let path = state.selected_path.clone();
+    let mime = menu_type(state, &path).mime;
+    for action in state.settings.actions.clone() {
+      if !action.applies_to(&path, &mime) {
+        continue;
+      }
+      if ui.button(format!("{} {}", action.icon, action.name)).clicked() {
+        let ctx = ui.ctx().clone();
+        let paths = state.selection.targets(&path);
+        if let Err(err) = actions::run(state, &ctx, &action, &paths) {
+          state.errors.report(err);
+        }
+        ui.close_menu();
+      }
+    }
+    ui.menu_button("Open with", |ui| {
+      open_with_menu(state, ui);
+    });
+    if ui.button("Properties").clicked() {
+      properties::open(state, state.selected_path.clone());
       ui.close_menu();
     }
+    ui.menu_button("Tags", |ui| {
+      let path = state.selected_path.clone();
+      let current = state.tags.known(&path);
+      ui.horizontal(|ui| {
+        for (label, [r, g, b]) in COLOR_LABELS {
+          let mut text = egui::RichText::new(if current.iter().any(|tag| tag == label) {
+            "●"
+          } else {
+            "○"
+          });
+          text = text.color(egui::Color32::from_rgb(r, g, b));
+          if ui.button(text).on_hover_text(label).clicked() {
+            tags::toggle(state, &path, label);
+          }
+        }
+      });
+      ui.separator();
+      let known: Vec<String> = state
+        .tags
+        .by_tag
+        .keys()
+        .filter(|tag| !COLOR_LABELS.iter().any(|(label, _)| label == tag))
+        .cloned()
+        .collect();
+      for tag in known {
+        let mut checked = current.contains(&tag);
+        if ui.checkbox(&mut checked, &tag).changed() {
+          tags::toggle(state, &path, &tag);
+        }
+      }
+      let input = ui.text_edit_singleline(&mut state.tags.input);
+      if input.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+        let tag = state.tags.input.trim().replace(',', " ");
+        if !tag.is_empty() && !current.contains(&tag) {
+          tags::toggle(state, &path, &tag);
+        }
+        state.tags.input = String::new();
+      }
+    });
+  }
+
+  fn open_with_menu(state: &mut Themis, ui: &mut egui::Ui) {
+    let path = state.selected_path.clone();
+    let paths = state.selection.targets(&path);
+    let mime = menu_type(state, &path).mime;
+    let default = state.applications.default_for(&mime).map(|app| app.id.clone());
+    let apps: Vec<open_with::DesktopApp> = state
+      .applications
+      .for_mime(&mime)
+      .into_iter()
+      .cloned()
+      .collect();
+    if apps.is_empty() {
+      ui.weak(format!("Nothing is registered for {}", mime));
+    }
+    for app in apps {
+      ui.horizontal(|ui| {
+        let is_default = default.as_deref() == Some(app.id.as_str());
+        let name = if is_default {
+          format!("{} (default)", app.name)
+        } else {
+          app.name.clone()
+        };
+        if ui.button(name).clicked() {
+          if let Err(err) = open_with::launch(&app, &paths) {
+            state.errors.report(err);
+          }
+          ui.close_menu();
+        }
+        if !is_default && ui.small_button("Set as default").clicked() {
+          if let Err(err) = state.applications.set_default(&mime, &app.id) {
+            state.errors.report(err);
+          }
+        }
+      });
+    }
+    ui.separator();
+    ui.horizontal(|ui| {
+      let input = ui.text_edit_singleline(&mut state.applications.command_input);
+      let entered = input.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+      if (ui.button("Run").clicked() || entered)
+        && !state.applications.command_input.trim().is_empty()
+      {
+        if let Err(err) = open_with::run_command(&state.applications.command_input, &paths) {
+          state.errors.report(err);
+        }
+        ui.close_menu();
+      }
+    });
+  }
+
+  /// The type from the contents, the listing mostly goes by the extension.
+  /// Read once per opened menu.
+  fn menu_type(state: &mut Themis, path: &std::path::Path) -> FileType {
+    if let Some((cached, file_type)) = &state.menu_type {
+      if cached == path {
+        return file_type.clone();
+      }
+    }
+    let is_dir = state
+      .dir_entries
+      .iter()
+      .chain(state.search_results.iter())
+      .find(|entry| entry.path == path)
+      .map_or_else(|| path.is_dir(), |entry| entry.is_dir);
+    let file_type = state.mime.detect(path, is_dir, false);
+    state.menu_type = Some((path.to_path_buf(), file_type.clone()));
+    file_type
+  }
+
+  fn tag_chip(ui: &mut egui::Ui, tag: &str) {
+    let [r, g, b] = tags::color(tag);
+    ui.label(
+      egui::RichText::new(format!(" {} ", tag))
+        .small()
+        .color(egui::Color32::BLACK)
+        .background_color(egui::Color32::from_rgb(r, g, b)),
+    );
   }
 }
@@ -0,0 +1,77 @@
+use eframe::egui;
+
+use crate::app::{PanelOpen, Themis};
+use crate::misc::name::escape;
+
+/// How many directories the dialog lists.
+const SHOWN: usize = 10;
+
+/// Ctrl+J, jumping to a visited directory by a few fragments of its path.
+pub fn dialog(ctx: &egui::Context, state: &mut Themis) {
+  if ctx.input_mut().consume_key(egui::Modifiers::COMMAND, egui::Key::J) {
+    state.jump.open = !state.jump.open;
+    state.jump.query.clear();
+    state.jump.selected = 0;
+  }
+  if !state.jump.open {
+    return;
+  }
+
+  let matches = state.jump.matches(&state.jump.query, SHOWN);
+  let shown = matches.len();
+  let mut target = None;
+  let mut open = true;
+  egui::Window::new("Jump to")
+    .open(&mut open)
+    .collapsible(false)
+    .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+    .show(ctx, |ui| {
+      let input = ui.add(
+        egui::TextEdit::singleline(&mut state.jump.query)
+          .desired_width(400.0)
+          .hint_text("Parts of the path, like: thm src"),
+      );
+      input.request_focus();
+      if input.changed() {
+        state.jump.selected = 0;
+      }
+      {
+        let mut keys = ui.input_mut();
+        if keys.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) && state.jump.selected + 1 < shown {
+          state.jump.selected += 1;
+        }
+        if keys.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
+          state.jump.selected = state.jump.selected.saturating_sub(1);
+        }
+      }
+      if ui.input().key_pressed(egui::Key::Enter) {
+        target = matches.get(state.jump.selected).map(|(path, _)| path.clone());
+      }
+      if ui.input().key_pressed(egui::Key::Escape) {
+        state.jump.open = false;
+      }
+
+      ui.separator();
+      if matches.is_empty() {
+        ui.label("No visited directory matches");
+      }
+      egui::Grid::new("jump_matches").show(ui, |ui| {
+        for (index, (path, score)) in matches.iter().enumerate() {
+          let label = ui.selectable_label(index == state.jump.selected, escape(path.as_os_str()));
+          if label.clicked() {
+            target = Some(path.clone());
+          }
+          ui.label(egui::RichText::new(format!("{:.1}", score)).weak());
+          ui.end_row();
+        }
+      });
+    });
+  if !open {
+    state.jump.open = false;
+  }
+  if let Some(path) = target {
+    state.current_path = path;
+    state.panel_open = PanelOpen::Main;
+    state.jump.open = false;
+  }
+}
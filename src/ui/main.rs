@@ -1,28 +1,48 @@
 use crate::app::Themis;
+use bytesize::ByteSize;
 use eframe::egui;
 use std::fs::read_dir;
 
+use super::bookmarks;
 use super::file_menu;
-use crate::misc::search::{update_current_dir, update_search};
+use crate::misc::filters::{self, Kind};
+use crate::misc::git;
+use crate::misc::name::escape;
+use crate::misc::views::{self, ViewMode};
+use crate::misc::tags;
+use crate::misc::search::{sort_entries, update_current_dir, update_search};
+use crate::ui::settings::SortMode;
+use crate::misc::watch;
 
 pub fn main(ctx: &egui::Context, state: &mut Themis) {
   if state.filesystem.files.is_empty() {
     let output = state.fs_receiver.try_recv();
-    if let Ok(output) = output {
-      state.filesystem = output;
-      update_current_dir(state);
+    if let Ok((filesystem, dir_sizes)) = output {
+      state.filesystem = filesystem;
+      state.dir_sizes = dir_sizes;
+      if let Err(err) = update_current_dir(state) {
+        state.errors.report(err);
+      }
     }
   }
 
-  let recv = state.dir_watcher.dir_watcher.try_recv();
-  if let Ok(event) = recv {
-    if event.kind == notify::EventKind::Create(notify::event::CreateKind::Any)
-      || event.kind == notify::EventKind::Remove(notify::event::RemoveKind::Any)
-    {
-      // println!("{:?}", event.paths);
-      println!("updating because file changed");
-      update_current_dir(state);
-    }
+  if let Err(err) = watch::poll(state) {
+    state.errors.report(err);
+  }
+  git::poll(state, ctx);
+  tags::poll(state, ctx);
+
+  // * Ctrl+scroll zooms the listing, Ctrl+0 goes back to normal
+  let zoom = ctx.input().zoom_delta();
+  if (zoom - 1.0).abs() > f32::EPSILON {
+    state.view.zoom = (state.view.zoom * zoom).clamp(views::MIN_ZOOM, views::MAX_ZOOM);
+  }
+  if ctx.input_mut().consume_key(egui::Modifiers::COMMAND, egui::Key::Num0) {
+    state.view.zoom = 1.0;
+  }
+  if watch::is_pending(state) {
+    // * Come back once the debounce window is over
+    ctx.request_repaint();
   }
 
   egui::SidePanel::left("side_panel").show(ctx, |ui| {
@@ -30,16 +50,70 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
       ui.heading("( ._.)");
     }
     
-    ui.heading("Pinned:");
-    for pin in state.pinned_dirs.clone() {
-      if ui.button(pin.to_str().unwrap()).clicked() {
-        state.current_path = pin;
+    bookmarks::sidebar(ui, state);
+    ui.heading("Places:");
+    for place in state.places.clone() {
+      let button = ui.button(&place.name).on_hover_text(escape(place.path.as_os_str()));
+      if button.clicked() {
+        state.current_path = place.path;
       }
     }
-    ui.heading("Drives:");
-    for drive in state.drive_list.clone() {
-      if ui.button(drive.to_str().unwrap()).clicked() {
-        state.current_path = std::path::PathBuf::from(drive);
+    if let Some(devices) = state.devices_receiver.try_iter().last() {
+      state.devices = devices;
+    }
+    if !state.devices.is_empty() {
+      ui.heading("Devices:");
+      for device in state.devices.clone() {
+        ui.horizontal(|ui| {
+          let button = ui
+            .button(&device.label)
+            .on_hover_text(format!("{} on {}", device.source, escape(device.mount_point.as_os_str())));
+          if button.clicked() {
+            state.current_path = device.mount_point.clone();
+          }
+          ui.label(egui::RichText::new(&device.fs_type).weak());
+        });
+        if device.total > 0 {
+          let used = 1.0 - device.free as f32 / device.total as f32;
+          ui.add(egui::ProgressBar::new(used).text(format!(
+            "{} free of {}",
+            ByteSize(device.free),
+            ByteSize(device.total)
+          )));
+        }
+      }
+    }
+    if !state.drive_list.is_empty() {
+      ui.heading("Drives:");
+      for drive in state.drive_list.clone() {
+        if ui.button(escape(&drive)).clicked() {
+          state.current_path = std::path::PathBuf::from(drive);
+        }
+      }
+    }
+    if !state.tags.by_tag.is_empty() {
+      ui.heading("Tags:");
+      for (tag, paths) in state.tags.by_tag.clone() {
+        let [r, g, b] = tags::color(&tag);
+        let title = egui::RichText::new(format!("{} ({})", tag, paths.len()))
+          .color(egui::Color32::from_rgb(r, g, b));
+        egui::CollapsingHeader::new(title)
+          .id_source(("tag", &tag))
+          .show(ui, |ui| {
+            for path in paths {
+              let name = escape(path.file_name().unwrap_or_else(|| path.as_os_str()));
+              let button = ui.button(name).on_hover_text(escape(path.as_os_str()));
+              if button.clicked() {
+                // * Folders open directly, files show up in their folder
+                if path.is_dir() {
+                  state.current_path = path;
+                } else if let Some(parent) = path.parent() {
+                  state.current_path = parent.to_path_buf();
+                  state.selected_path = path.clone();
+                }
+              }
+            }
+          });
       }
     }
 
@@ -62,14 +136,14 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
       let test = std::path::PathBuf::from(state.navigation.clone());
       let mut searchable_path = std::path::PathBuf::default();
       for (index, path) in test.iter().enumerate() {
-        if index != 1 || path.to_str().unwrap() != "\\" {
+        if index != 1 || path != "\\" {
           searchable_path.push(path);
           if index == 0 {
             searchable_path.push("\\");
           }
           ui.label("▶");
           let dir =
-            ui.add(egui::Label::new(path.to_str().unwrap_or_default()).sense(egui::Sense::click()));
+            ui.add(egui::Label::new(escape(path)).sense(egui::Sense::click()));
           let popup_id = ui.make_persistent_id(searchable_path.clone());
           if dir.clicked() {
             ui.memory().toggle_popup(popup_id);
@@ -77,12 +151,9 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
           egui::popup::popup_below_widget(ui, popup_id, &dir, |ui| {
             ui.set_width(150.0);
             if let Ok(popup_dir) = read_dir(searchable_path.clone()) {
-              for dir in popup_dir {
-                let dir = dir.unwrap();
+              for dir in popup_dir.flatten() {
                 let dir_path = dir.path();
-                if dir.metadata().unwrap().is_dir()
-                  && ui.button(dir.file_name().to_str().unwrap()).clicked()
-                {
+                if dir_path.is_dir() && ui.button(escape(&dir.file_name())).clicked() {
                   state.current_path = dir_path;
                 }
               }
@@ -90,6 +161,19 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
           });
         }
       }
+      if let Some(repo) = &state.git.repo {
+        let mut branch = format!("  ⎇ {}", repo.branch);
+        if let Some((ahead, behind)) = repo.ahead_behind {
+          if ahead > 0 {
+            branch.push_str(&format!(" ↑{}", ahead));
+          }
+          if behind > 0 {
+            branch.push_str(&format!(" ↓{}", behind));
+          }
+        }
+        ui.label(egui::RichText::new(branch).weak())
+          .on_hover_text(escape(repo.workdir.as_os_str()));
+      }
     });
     ui.end_row();
     ui.horizontal(|ui| {
@@ -98,18 +182,24 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
 
       if navigation.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
         state.current_path = std::path::PathBuf::from(state.navigation.clone());
-        update_current_dir(state);
+        if let Err(err) = update_current_dir(state) {
+          state.errors.report(err);
+        }
         // * Very important piece of logic that needs to be moved
       } else if state.current_path != state.last_path {
         println!("updating because path changed");
-        update_current_dir(state);
+        if let Err(err) = update_current_dir(state) {
+          state.errors.report(err);
+        }
       }
 
       // * Search bar
       let search = ui.text_edit_singleline(&mut state.search);
-      if search.changed() && state.search != "" {
+      if search.changed() && !state.search.is_empty() {
         println!("updating because of search");
-        update_search(state);
+        if let Err(err) = update_search(state) {
+          state.errors.report(err);
+        }
       }
     });
 
@@ -117,19 +207,34 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
 
     ui.horizontal(|ui| {
       if ui.button("Go up").clicked() {
-        state.current_path = state.current_path.parent().unwrap().to_path_buf();
+        if let Some(parent) = state.current_path.parent() {
+          state.current_path = parent.to_path_buf();
+        }
       }
       if ui.button("Go back").clicked() {
         state.current_path = state.last_path.to_path_buf();
       }
-      if state.pinned_dirs.contains(&state.current_path) {
-        if ui.button("Unpin directory").clicked() {
-          state
-            .pinned_dirs
-            .retain(|x| x != &state.current_path.clone());
+      let sort_mode = state.view.sort_mode;
+      let sort_descending = state.view.sort_descending;
+      egui::ComboBox::from_id_source("sort_mode")
+        .selected_text(format!("Sort: {:?}", state.view.sort_mode))
+        .show_ui(ui, |ui| {
+          ui.selectable_value(&mut state.view.sort_mode, SortMode::Name, "Name");
+          ui.selectable_value(&mut state.view.sort_mode, SortMode::Size, "Size");
+          ui.selectable_value(&mut state.view.sort_mode, SortMode::Files, "Files");
+        });
+      ui.checkbox(&mut state.view.sort_descending, "Descending");
+      if sort_mode != state.view.sort_mode || sort_descending != state.view.sort_descending {
+        sort_entries(&mut state.dir_entries, &state.view);
+        sort_entries(&mut state.search_results, &state.view);
+      }
+      ui.menu_button("View", |ui| view_menu(ui, state));
+      if state.bookmarks.contains(&state.current_path) {
+        if ui.button("Remove bookmark").clicked() {
+          state.bookmarks.remove(&state.current_path);
         }
-      } else if ui.button("Pin directory").clicked() {
-        state.pinned_dirs.push(state.current_path.to_path_buf());
+      } else if ui.button("Bookmark directory").clicked() {
+        state.bookmarks.add(state.current_path.to_path_buf());
       }
       // if ui.button("New directory").clicked() {
       //   let new_dir_path = state.current_path.join(state.rename_bar.clone());
@@ -141,7 +246,12 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
       // }
     });
     ui.end_row();
+    filter_bar(ui, state);
+    ui.end_row();
     egui::ScrollArea::vertical().show(ui, |ui| {
+      for font in ui.style_mut().text_styles.values_mut() {
+        font.size *= state.view.zoom;
+      }
       egui::Grid::new("central_grid").show(ui, |ui| {
         ui.end_row();
         ui.spacing_mut().item_spacing.y = 1.5;
@@ -159,3 +269,70 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
     });
   }
 }
+
+/// Narrows the listing without searching, remembered per directory.
+fn filter_bar(ui: &mut egui::Ui, state: &mut Themis) {
+  let filter = &mut state.view.filter;
+  ui.horizontal(|ui| {
+    ui.add(
+      egui::TextEdit::singleline(&mut filter.quick)
+        .hint_text("Filter this folder")
+        .desired_width(150.0),
+    );
+    let extensions = filters::extensions(&state.dir_entries);
+    let selected = if filter.extension.is_empty() {
+      "All types".to_owned()
+    } else {
+      format!(".{}", filter.extension)
+    };
+    egui::ComboBox::from_id_source("extension_filter")
+      .selected_text(selected)
+      .show_ui(ui, |ui| {
+        ui.selectable_value(&mut filter.extension, "".to_owned(), "All types");
+        for (extension, count) in extensions {
+          let label = format!(".{} ({})", extension, count);
+          ui.selectable_value(&mut filter.extension, extension, label);
+        }
+      });
+    ui.selectable_value(&mut filter.kind, Kind::All, "All");
+    ui.selectable_value(&mut filter.kind, Kind::Files, "Files");
+    ui.selectable_value(&mut filter.kind, Kind::Dirs, "Folders");
+    ui.checkbox(&mut filter.show_hidden, "Hidden")
+      .on_hover_text("Hidden files in this listing, search has its own setting");
+    ui.checkbox(&mut filter.show_backups, "Backups");
+    if !filter.is_default() && ui.button("Clear filters").clicked() {
+      *filter = filters::ListingFilter::default();
+    }
+  });
+}
+
+/// Layout, columns and zoom of the current directory's view.
+fn view_menu(ui: &mut egui::Ui, state: &mut Themis) {
+  let view = &mut state.view;
+  ui.horizontal(|ui| {
+    ui.selectable_value(&mut view.mode, ViewMode::List, "List");
+    ui.selectable_value(&mut view.mode, ViewMode::Grid, "Grid");
+  });
+  ui.add_enabled_ui(view.mode == ViewMode::List, |ui| {
+    ui.checkbox(&mut view.columns.size, "Size");
+    ui.checkbox(&mut view.columns.details, "Details");
+    ui.checkbox(&mut view.columns.git, "Git status");
+    ui.checkbox(&mut view.columns.tags, "Tags");
+  });
+  ui.add(egui::Slider::new(&mut view.zoom, views::MIN_ZOOM..=views::MAX_ZOOM).text("Zoom"));
+  ui.checkbox(&mut view.inherit, "Use for subfolders");
+  ui.separator();
+  let source = match &state.views.source {
+    Some(source) if source == &state.current_path => "Saved for this folder".to_owned(),
+    Some(source) => format!("From {}", escape(source.as_os_str())),
+    None => "Default view".to_owned(),
+  };
+  ui.label(egui::RichText::new(source).weak());
+  if ui.button("Reset to default").clicked() {
+    if let Err(err) = views::reset(state) {
+      state.errors.report(err);
+    }
+    sort_entries(&mut state.dir_entries, &state.view);
+    ui.close_menu();
+  }
+}
@@ -1,27 +1,85 @@
 use crate::app::{PanelOpen, Themis};
 use eframe::egui;
 
+mod bookmarks;
+mod cleanup;
+mod conflict;
+mod disk_usage;
+mod duplicates;
+mod errors;
 mod file_menu;
+mod jump;
 mod main;
+mod output;
+mod properties;
 pub mod settings;
 use file_menu::file_menu;
 
 pub fn main(ctx: &egui::Context, state: &mut Themis) {
+  state.errors.poll();
+  crate::misc::ipc::poll(state);
+  if let Some(path) = state.bookmarks.shortcut(ctx) {
+    state.current_path = path;
+    state.panel_open = PanelOpen::Main;
+  }
+
   egui::TopBottomPanel::top("top_pannel").show(ctx, |ui| {
-    if state.panel_open == PanelOpen::Main {
-      if ui.button("Settings").clicked() {
+    ui.horizontal(|ui| {
+      if state.panel_open != PanelOpen::Main && ui.button("File Menu").clicked() {
+        state.panel_open = PanelOpen::Main;
+      }
+      if state.panel_open != PanelOpen::Settings && ui.button("Settings").clicked() {
         state.panel_open = PanelOpen::Settings;
       }
-    } else if state.panel_open == PanelOpen::Settings {
-      if ui.button("File Menu").clicked() {
-        state.panel_open = PanelOpen::Main;
+      if state.panel_open != PanelOpen::DiskUsage && ui.button("Disk Usage").clicked() {
+        state.disk_usage.root = None;
+        state.panel_open = PanelOpen::DiskUsage;
+      }
+      if state.panel_open != PanelOpen::Duplicates && ui.button("Duplicates").clicked() {
+        state.panel_open = PanelOpen::Duplicates;
       }
-    }
+      if state.panel_open != PanelOpen::Cleanup && ui.button("Cleanup").clicked() {
+        state.panel_open = PanelOpen::Cleanup;
+      }
+      if ui.selectable_label(state.jump.open, "Jump").on_hover_text("Ctrl+J").clicked() {
+        state.jump.open = !state.jump.open;
+      }
+      let running = state.output.runs.iter().filter(|run| run.exit.is_none()).count();
+      let output = if running == 0 {
+        "Output".to_owned()
+      } else {
+        format!("Output ({} running)", running)
+      };
+      if ui.selectable_label(state.output.open, output).clicked() {
+        state.output.open = !state.output.open;
+      }
+      let errors = format!("Errors ({})", state.errors.entries.len());
+      if ui.selectable_label(state.errors.open, errors).clicked() {
+        state.errors.open = !state.errors.open;
+      }
+    });
   });
 
+  crate::misc::output::poll(state);
+  errors::log(ctx, state);
+  output::command_bar(ctx, state);
+  output::panel(ctx, state);
+
   if state.panel_open == PanelOpen::Main {
     main::main(ctx, state);
   } else if state.panel_open == PanelOpen::Settings {
     settings::main(ctx, state);
+  } else if state.panel_open == PanelOpen::DiskUsage {
+    disk_usage::main(ctx, state);
+  } else if state.panel_open == PanelOpen::Duplicates {
+    duplicates::main(ctx, state);
+  } else if state.panel_open == PanelOpen::Cleanup {
+    cleanup::main(ctx, state);
   }
+
+  conflict::main(ctx, state);
+  properties::main(ctx, state);
+  bookmarks::editor(ctx, state);
+  jump::dialog(ctx, state);
+  errors::toasts(ctx, state);
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+use eframe::egui;
+
+use crate::app::Themis;
+use crate::misc::ansi;
+use crate::misc::output;
+
+/// The command bar, opened with `:` or Ctrl+` and running in the current
+/// directory.
+pub fn command_bar(ctx: &egui::Context, state: &mut Themis) {
+  let command = ctx.input().modifiers.command;
+  let opens = |event: &egui::Event| match event {
+    egui::Event::Text(text) => text == ":" || (command && text == "`"),
+    _ => false,
+  };
+  if (command || !ctx.wants_keyboard_input()) && ctx.input().events.iter().any(opens) {
+    // * Swallow it, so it doesn't end up in the freshly focused bar
+    ctx.input_mut().events.retain(|event| !opens(event));
+    state.output.command_open = true;
+  }
+  if !state.output.command_open {
+    return;
+  }
+  egui::TopBottomPanel::bottom("command_bar").show(ctx, |ui| {
+    ui.horizontal(|ui| {
+      ui.monospace(":");
+      let input = ui.add(
+        egui::TextEdit::singleline(&mut state.output.command_input)
+          .code_editor()
+          .desired_width(f32::INFINITY)
+          .hint_text("Shell command, selected files are in $FILES"),
+      );
+      if ui.input().key_pressed(egui::Key::Escape) {
+        state.output.command_open = false;
+      } else if input.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+        let script = std::mem::take(&mut state.output.command_input);
+        if !script.trim().is_empty() {
+          if let Err(err) = output::run_command(state, ctx, &script) {
+            state.errors.report(err);
+          }
+        }
+        state.output.command_open = false;
+      } else {
+        input.request_focus();
+      }
+    });
+  });
+}
+
+pub fn panel(ctx: &egui::Context, state: &mut Themis) {
+  if !state.output.open {
+    return;
+  }
+  egui::TopBottomPanel::bottom("output_panel")
+    .resizable(true)
+    .show(ctx, |ui| {
+      ui.horizontal(|ui| {
+        ui.heading("Output");
+        if ui.button("Clear finished").clicked() {
+          state.output.runs.retain(|run| run.exit.is_none());
+        }
+        if ui.button("Close").clicked() {
+          state.output.open = false;
+        }
+      });
+      egui::ScrollArea::vertical()
+        .stick_to_bottom()
+        .show(ui, |ui| {
+          for (index, run) in state.output.runs.iter().enumerate().filter(|(_, run)| run.show) {
+            let status = match run.exit {
+              None => "running".to_owned(),
+              Some(Some(code)) => format!("exit {}", code),
+              Some(None) => "killed".to_owned(),
+            };
+            egui::CollapsingHeader::new(format!("{} ({})", run.title, status))
+              .id_source(("output_run", index))
+              .default_open(true)
+              .show(ui, |ui| {
+                if run.exit.is_none() && ui.button("Cancel").clicked() {
+                  run.cancel();
+                }
+                let font = egui::TextStyle::Monospace.resolve(ui.style());
+                let color = ui.visuals().text_color();
+                ui.label(ansi::layout(&run.text, font, color));
+              });
+          }
+        });
+    });
+}
@@ -0,0 +1,176 @@
+use bytesize::ByteSize;
+use eframe::egui;
+use std::time::SystemTime;
+
+use crate::app::Themis;
+use crate::misc::name::escape;
+use crate::misc::properties::{self, format_time};
+
+enum Edit {
+  Mode,
+  Owner,
+  Times,
+}
+
+pub fn main(ctx: &egui::Context, state: &mut Themis) {
+  let mut open = state.properties.is_some();
+  let mut edit = None;
+  let properties = match &mut state.properties {
+    Some(properties) => properties,
+    None => return,
+  };
+  egui::Window::new("Properties")
+    .open(&mut open)
+    .resizable(false)
+    .show(ctx, |ui| {
+      let info = &properties.info;
+      egui::Grid::new("properties_grid").num_columns(2).show(ui, |ui| {
+        ui.label("Path");
+        ui.label(escape(properties.path.as_os_str()));
+        ui.end_row();
+        if let Some(target) = &info.symlink_target {
+          ui.label("Links to");
+          ui.label(escape(target.as_os_str()));
+          ui.end_row();
+        }
+        ui.label("Type");
+        let file_type = &info.file_type;
+        ui.label(format!(
+          "{} {} ({})",
+          file_type.icon, file_type.description, file_type.mime
+        ));
+        ui.end_row();
+        ui.label("Size");
+        ui.label(format!("{} ({} bytes)", ByteSize(info.size), info.size));
+        ui.end_row();
+        if let Some(allocated) = info.allocated {
+          ui.label("On disk");
+          ui.label(ByteSize(allocated).to_string());
+          ui.end_row();
+        }
+        time_row(ui, "Created", info.created);
+        time_row(ui, "Modified", info.modified);
+        time_row(ui, "Accessed", info.accessed);
+        time_row(ui, "Changed", info.changed);
+        if let Some(inode) = info.inode {
+          ui.label("Inode");
+          ui.label(inode.to_string());
+          ui.end_row();
+        }
+        if let Some(links) = info.links {
+          ui.label("Links");
+          ui.label(links.to_string());
+          ui.end_row();
+        }
+        if let (Some((uid, owner)), Some((gid, group))) = (&info.owner, &info.group) {
+          ui.label("Owner");
+          ui.label(format!("{} ({}) / {} ({})", owner, uid, group, gid));
+          ui.end_row();
+        }
+      });
+
+      if info.mode.is_some() {
+        ui.separator();
+        ui.heading("Permissions");
+        egui::Grid::new("permissions_grid").show(ui, |ui| {
+          ui.label("");
+          ui.label("Read");
+          ui.label("Write");
+          ui.label("Execute");
+          ui.end_row();
+          for (row, shift) in [("Owner", 6), ("Group", 3), ("Others", 0)] {
+            ui.label(row);
+            for bit in [4, 2, 1] {
+              let mask = bit << shift;
+              let mut set = properties.mode & mask != 0;
+              if ui.checkbox(&mut set, "").changed() {
+                properties.mode ^= mask;
+              }
+            }
+            ui.end_row();
+          }
+        });
+        ui.horizontal(|ui| {
+          ui.monospace(format!("{:04o}", properties.mode));
+          if info.is_dir {
+            ui.checkbox(&mut properties.recursive, "Apply to everything inside");
+          }
+          if ui.button("Apply permissions").clicked() {
+            edit = Some(Edit::Mode);
+          }
+        });
+
+        ui.horizontal(|ui| {
+          ui.label("Owner");
+          ui.add(egui::TextEdit::singleline(&mut properties.owner_input).desired_width(80.0));
+          ui.label("Group");
+          ui.add(egui::TextEdit::singleline(&mut properties.group_input).desired_width(80.0));
+          if ui.button("Change owner").clicked() {
+            edit = Some(Edit::Owner);
+          }
+        });
+      }
+
+      ui.separator();
+      ui.heading("Timestamps (UTC)");
+      egui::Grid::new("timestamps_grid").show(ui, |ui| {
+        time_input(ui, "Modified", &mut properties.modified_input);
+        time_input(ui, "Accessed", &mut properties.accessed_input);
+      });
+      if ui.button("Apply timestamps").clicked() {
+        edit = Some(Edit::Times);
+      }
+
+      if !properties.info.xattrs.is_empty() {
+        ui.separator();
+        ui.collapsing("Extended attributes", |ui| {
+          egui::Grid::new("xattr_grid").show(ui, |ui| {
+            for (name, value) in &properties.info.xattrs {
+              ui.monospace(name);
+              ui.label(value);
+              ui.end_row();
+            }
+          });
+        });
+      }
+    });
+
+  let result = match edit {
+    Some(Edit::Mode) => properties::set_mode(&properties.path, properties.mode, properties.recursive),
+    Some(Edit::Owner) => {
+      properties::set_owner(&properties.path, &properties.owner_input, &properties.group_input)
+    }
+    Some(Edit::Times) => properties::set_times(
+      &properties.path,
+      &properties.accessed_input,
+      &properties.modified_input,
+    ),
+    None => Ok(()),
+  };
+  if let Err(err) = result {
+    state.errors.report(err);
+  }
+  if !open {
+    state.properties = None;
+  } else if edit.is_some() {
+    properties::reload(state);
+  }
+}
+
+fn time_row(ui: &mut egui::Ui, label: &str, time: Option<SystemTime>) {
+  if let Some(time) = time {
+    ui.label(label);
+    ui.label(format_time(time));
+    ui.end_row();
+  }
+}
+
+fn time_input(ui: &mut egui::Ui, label: &str, input: &mut String) {
+  ui.label(label);
+  if properties::parse_time(input).is_none() {
+    ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+  }
+  ui.text_edit_singleline(input);
+  ui.visuals_mut().override_text_color = None;
+  ui.end_row();
+}
@@ -1,13 +1,20 @@
+use bytesize::ByteSize;
 use eframe::egui;
+use glob::Pattern;
 use std::env::current_dir;
 use std::path::PathBuf;
 
 use crate::app::Themis;
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct Settings {
   pub search: SearchSettings,
   pub save_load: SaveLoadSettings,
+  pub cleanup: CleanupSettings,
+  pub actions: Vec<CustomAction>,
+  /// Answer "show in folder" requests from other apps over D-Bus.
+  pub file_manager_service: bool,
   pub show_francis: bool,
 }
 
@@ -16,15 +23,20 @@ impl Default for Settings {
     Self {
       search: SearchSettings::default(),
       save_load: SaveLoadSettings::default(),
+      cleanup: CleanupSettings::default(),
+      actions: Vec::new(),
+      file_manager_service: false,
       show_francis: true,
     }
   }
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct SaveLoadSettings {
   pub location_input: String,
   pub location_is_valid: bool,
+  #[serde(with = "crate::misc::name::saved_path")]
   pub location: PathBuf,
 }
 
@@ -33,17 +45,96 @@ impl Default for SaveLoadSettings {
     Self {
       location_input: "".to_owned(),
       location_is_valid: true,
-      location: current_dir().unwrap(),
+      location: current_dir().unwrap_or_default(),
     }
   }
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CleanupSettings {
+  /// Globs for temporary files.
+  #[serde(deserialize_with = "globs")]
+  pub temp_globs: Vec<String>,
+  /// Temporary files untouched for this long count as stale.
+  pub stale_days: u64,
+}
+
+impl Default for CleanupSettings {
+  fn default() -> Self {
+    Self {
+      temp_globs: ["*.tmp", "*.temp", "*~", ".#*", "*.swp", "*.part", "*.crdownload"]
+        .iter()
+        .map(|glob| glob.to_string())
+        .collect(),
+      stale_days: 7,
+    }
+  }
+}
+
+/// A context menu entry that runs a shell command, see `misc::actions`.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CustomAction {
+  pub name: String,
+  pub icon: String,
+  /// Shell command with `{path}`, `{paths}`, `{dir}`, `{name}` and `{stem}`.
+  pub command: String,
+  /// Globs for names or MIME types, empty matches everything.
+  pub condition: String,
+  pub terminal: bool,
+  pub capture_output: bool,
+  pub refresh: bool,
+}
+
+impl Default for CustomAction {
+  fn default() -> Self {
+    Self {
+      name: "New action".to_owned(),
+      icon: "▶".to_owned(),
+      command: "echo {paths}".to_owned(),
+      condition: "".to_owned(),
+      terminal: false,
+      capture_output: true,
+      refresh: false,
+    }
+  }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct SearchSettings {
   pub search_mode: SearchMode,
   pub sensitive: bool,
   pub recursive: bool,
   pub match_mode: MatchMode,
+  pub min_size_input: String,
+  pub min_size: Option<u64>,
+  pub max_size_input: String,
+  pub max_size: Option<u64>,
+  /// Leave out what `.gitignore`, `.ignore` and the global excludes file
+  /// ignore, both when searching and when building the index.
+  pub respect_ignore: bool,
+  /// Globs for names to leave out.
+  #[serde(deserialize_with = "globs")]
+  pub exclude_globs: Vec<String>,
+  /// Only for search results, the listing has its own toggle in the filter
+  /// bar.
+  pub show_hidden: bool,
+}
+
+/// Older settings kept glob lists in one whitespace separated string.
+fn globs<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+  #[derive(serde::Deserialize)]
+  #[serde(untagged)]
+  enum Globs {
+    List(Vec<String>),
+    Text(String),
+  }
+  Ok(match serde::Deserialize::deserialize(deserializer)? {
+    Globs::List(globs) => globs,
+    Globs::Text(text) => text.split_whitespace().map(str::to_owned).collect(),
+  })
 }
 
 impl Default for SearchSettings {
@@ -53,6 +144,13 @@ impl Default for SearchSettings {
       sensitive: false,
       recursive: false,
       match_mode: MatchMode::Normal,
+      min_size_input: "".to_owned(),
+      min_size: None,
+      max_size_input: "".to_owned(),
+      max_size: None,
+      respect_ignore: false,
+      exclude_globs: Vec::new(),
+      show_hidden: true,
     }
   }
 }
@@ -64,11 +162,19 @@ pub enum MatchMode {
   Strict,
 }
 
+#[derive(Clone, Copy, serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+pub enum SortMode {
+  Name,
+  Size,
+  Files,
+}
+
 #[derive(Clone, serde::Deserialize, serde::Serialize, Debug, PartialEq)]
 pub enum SearchMode {
   Glob,
   Regex,
   Contains,
+  Fuzzy,
 }
 
 pub fn main(ctx: &egui::Context, state: &mut Themis) {
@@ -109,12 +215,31 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
           SearchMode::Contains,
           "Contains",
         );
+        ui.selectable_value(
+          &mut state.settings.search.search_mode,
+          SearchMode::Fuzzy,
+          "Fuzzy",
+        );
       });
     ui.checkbox(
       &mut state.settings.search.sensitive,
       "Search case sensitivity",
     );
     ui.checkbox(&mut state.settings.search.recursive, "Search recursive");
+    ui.checkbox(&mut state.settings.search.show_hidden, "Include hidden files in search results")
+      .on_hover_text("The listing has its own Hidden toggle in the filter bar");
+    ui.checkbox(
+      &mut state.settings.search.respect_ignore,
+      "Respect .gitignore and .ignore files",
+    )
+    .on_hover_text("Also applies to the index, ignored files then don't count toward folder sizes");
+    ui.label("Excluded from search");
+    glob_list(
+      ui,
+      &mut state.settings.search.exclude_globs,
+      "A name like node_modules or *.o",
+      "Add exclude",
+    );
     egui::ComboBox::from_label("Match Mode")
       .selected_text(format!("{:?}", state.settings.search.search_mode))
       .show_ui(ui, |ui| {
@@ -135,6 +260,19 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
         );
       });
 
+    size_input(
+      ui,
+      &mut state.settings.search.min_size_input,
+      &mut state.settings.search.min_size,
+      "Search minimum size",
+    );
+    size_input(
+      ui,
+      &mut state.settings.search.max_size_input,
+      &mut state.settings.search.max_size,
+      "Search maximum size",
+    );
+
     ui.horizontal(|ui| {
       if state.settings.save_load.location_is_valid {
         ui.visuals_mut().override_text_color = Some(egui::Color32::LIGHT_GREEN);
@@ -158,6 +296,137 @@ pub fn main(ctx: &egui::Context, state: &mut Themis) {
       }
     });
 
+    ui.label("Temporary file patterns");
+    glob_list(
+      ui,
+      &mut state.settings.cleanup.temp_globs,
+      "A name like *.tmp or .#*",
+      "Add pattern",
+    );
+    ui.horizontal(|ui| {
+      ui.add(
+        egui::DragValue::new(&mut state.settings.cleanup.stale_days)
+          .clamp_range(0..=36500)
+          .suffix(" days"),
+      );
+      ui.label("Temporary files count as stale after");
+    });
+
     ui.checkbox(&mut state.settings.show_francis, "Show Francis");
+
+    #[cfg(target_os = "linux")]
+    if ui
+      .checkbox(
+        &mut state.settings.file_manager_service,
+        "Act as the system file manager (org.freedesktop.FileManager1)",
+      )
+      .changed()
+    {
+      // * Dropping the connection gives the name back
+      state.file_manager = None;
+      if state.settings.file_manager_service {
+        match crate::misc::file_manager::start(ctx.clone(), state.ipc_sender.clone()) {
+          Ok(connection) => state.file_manager = Some(connection),
+          Err(err) => {
+            state.settings.file_manager_service = false;
+            state.errors.report(err);
+          }
+        }
+      }
+    }
+
+    ui.separator();
+    ui.heading("Custom actions");
+    let mut remove = None;
+    for (index, action) in state.settings.actions.iter_mut().enumerate() {
+      egui::CollapsingHeader::new(format!("{} {}", action.icon, action.name))
+        .id_source(("custom_action", index))
+        .show(ui, |ui| {
+          egui::Grid::new(("custom_action_grid", index)).show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut action.name);
+            ui.end_row();
+            ui.label("Icon");
+            ui.text_edit_singleline(&mut action.icon);
+            ui.end_row();
+            ui.label("Command");
+            ui.text_edit_singleline(&mut action.command)
+              .on_hover_text("{path}, {paths}, {dir}, {name} and {stem} are filled in");
+            ui.end_row();
+            ui.label("Show for");
+            ui.text_edit_singleline(&mut action.condition)
+              .on_hover_text("Globs like *.rs or image/*, empty shows it everywhere");
+            ui.end_row();
+          });
+          ui.checkbox(&mut action.terminal, "Run in a terminal");
+          ui.checkbox(&mut action.capture_output, "Show output");
+          ui.checkbox(&mut action.refresh, "Refresh the directory afterwards");
+          if ui.button("Remove").clicked() {
+            remove = Some(index);
+          }
+        });
+    }
+    if let Some(index) = remove {
+      state.settings.actions.remove(index);
+    }
+    if ui.button("Add action").clicked() {
+      state.settings.actions.push(CustomAction::default());
+    }
+  });
+}
+
+/// One row per glob, invalid ones in red, with a button to add another.
+fn glob_list(ui: &mut egui::Ui, globs: &mut Vec<String>, hint: &str, add: &str) {
+  let mut remove = None;
+  for (index, glob) in globs.iter_mut().enumerate() {
+    ui.horizontal(|ui| {
+      if Pattern::new(glob).is_err() {
+        ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+      }
+      ui.text_edit_singleline(glob).on_hover_text(hint);
+      if ui.small_button("✖").clicked() {
+        remove = Some(index);
+      }
+    });
+  }
+  if let Some(index) = remove {
+    globs.remove(index);
+  }
+  if ui.button(add).clicked() {
+    globs.push(String::new());
+  }
+}
+
+/// A size text box like "10 MB", empty means no limit.
+fn size_input(ui: &mut egui::Ui, input: &mut String, size: &mut Option<u64>, label: &str) {
+  ui.horizontal(|ui| {
+    let parsed = input.trim().parse::<ByteSize>();
+    if !input.trim().is_empty() && parsed.is_err() {
+      ui.visuals_mut().override_text_color = Some(egui::Color32::RED);
+    }
+    if ui.text_edit_singleline(input).changed() {
+      *size = input.trim().parse::<ByteSize>().ok().map(|size| size.as_u64());
+    }
+    ui.label(label);
   });
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn exclude_globs_read_the_old_string() {
+    let old: SearchSettings = serde_json::from_str(r#"{"exclude_globs": "node_modules  *.o"}"#).unwrap();
+    assert_eq!(old.exclude_globs, vec!["node_modules", "*.o"]);
+    let new: SearchSettings = serde_json::from_str(r#"{"exclude_globs": ["My Documents"]}"#).unwrap();
+    assert_eq!(new.exclude_globs, vec!["My Documents"]);
+  }
+
+  #[test]
+  fn temp_globs_read_the_old_string() {
+    let old: CleanupSettings = serde_json::from_str(r#"{"temp_globs": "*.tmp *~"}"#).unwrap();
+    assert_eq!(old.temp_globs, vec!["*.tmp", "*~"]);
+    assert_eq!(old.stale_days, 7);
+  }
+}